@@ -2,12 +2,15 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 #![allow(dead_code)]
 
-use std::collections::HashSet;
+use crate::sync;
+
+use std::cell::{Cell, RefCell};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::Ordering;
-use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize};
-use std::sync::Arc;
+#[cfg(not(all(test, loom)))]
+use sync::WithMut;
+use sync::{const_static, loom_const_fn, thread, thread_local};
+use sync::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 
 // Writer wraps T into HazPtrObjectWrapper<T> and Box into AtomicPtr.
 // T -> HazPtrObjectWrapper<T> -> Box::into_raw(Box::New) -> swap into AtomicPtr.
@@ -93,6 +96,9 @@ impl<T> Reclaim for T {}
 //}
 
 pub trait Deleter {
+    /// # Safety
+    ///
+    /// ptr is what the object was retired as, unreachable and unprotected, deleted once.
     unsafe fn delete(&self, ptr: *mut dyn Reclaim);
 }
 // impls Deleter trait to fn pointer type that takes *mut dyn Reclaim as arg.
@@ -104,6 +110,9 @@ impl Deleter for unsafe fn(*mut (dyn Reclaim + 'static)) {
         }
     }
 }
+// safe fns, so they coerce to the fn(*mut dyn Reclaim) retire takes. Only the domain calls
+// them, on the ptr they were retired with.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub mod deleters {
     use super::Reclaim;
     // we impled Deleter trait for fn(*mut dyn Reclaim)
@@ -125,24 +134,39 @@ pub mod deleters {
     pub const drop_box: unsafe fn(*mut dyn Reclaim) = _drop_box;
 }
 
+// retire APIs take either flavour of deleter:
+// retire(fn(*mut dyn Reclaim)), e.g. deleters::_drop_box, or
+// retire_with(&'static dyn Deleter), e.g. &deleters::drop_box.
+#[derive(Clone, Copy)]
+enum RetiredDeleter {
+    Fn(fn(*mut dyn Reclaim)),   // a fn pointer that takes a mut pointer to trait Reclaim
+    Dyn(&'static dyn Deleter),  // trait object fat pointer.
+}
+impl RetiredDeleter {
+    unsafe fn delete(self, ptr: *mut dyn Reclaim) {
+        match self {
+            RetiredDeleter::Fn(deleter) => deleter(ptr),
+            RetiredDeleter::Dyn(deleter) => unsafe { deleter.delete(ptr) },
+        }
+    }
+}
+
 // wrap an AtomicPtr swapped out *mut T into a Retired boxed in the RetiredList in a domain.
 pub struct Retired {
     ptr: *mut dyn Reclaim, // ptr is Box::into_raw of a HazPtrObject that impls Reclaim. must take exclusive for Box::from_raw()
-    // deleter: &'static dyn Deleter, // takes trait object pointer &dyn Deleter object
-    deleter: fn(*mut dyn Reclaim),  // a fn pointer that takes a mut pointer to trait Reclaim
+    deleter: RetiredDeleter,
     next: AtomicPtr<Retired>,  // *mut T=Retired from Box::into_raw
 }
 impl Retired {
     // _ domain provides 'domain lifetime to Retired.
-    pub fn new<'domain, F>(
+    fn new<'domain, F>(
         _: &'domain HazPtrDomain<F>,
         ptr: *mut (dyn Reclaim + 'domain),
-        // deleter: &'static dyn Deleter,
-        deleter: fn(*mut dyn Reclaim),
+        deleter: RetiredDeleter,
     ) -> Self {
         Self {
             // re-interpret cast lifetime 'domain => 'static
-            ptr: unsafe { std::mem::transmute::<_, *mut (dyn Reclaim + 'static)>(ptr) },
+            ptr: unsafe { std::mem::transmute::<*mut (dyn Reclaim + 'domain), *mut (dyn Reclaim + 'static)>(ptr) },
             deleter,
             next: AtomicPtr::new(std::ptr::null_mut()), // AtomicPtr take *mut T;
         }
@@ -150,7 +174,58 @@ impl Retired {
 }
 pub struct RetiredList {
     head: AtomicPtr<Retired>,  // linked list to *mut Retired
-    count: AtomicUsize,
+    count: AtomicUsize,        // Retired nodes currently linked in this list
+}
+impl RetiredList {
+    loom_const_fn! {
+        fn new() -> Self {
+            Self {
+                head: AtomicPtr::new(std::ptr::null_mut()),
+                count: AtomicUsize::new(0),
+            }
+        }
+    }
+    // stick a private chain first -> .. -> last of n nodes in front of head with one CAS.
+    fn push(&self, first: *mut Retired, last: *mut Retired, n: usize) {
+        self.count.fetch_add(n, Ordering::SeqCst);
+        let last = unsafe { &*last };
+        let mut head_ptr = self.head.load(Ordering::SeqCst);
+        loop {
+            last.next.store(head_ptr, Ordering::SeqCst);
+            match self.head.compare_exchange_weak(
+                head_ptr,
+                first,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => { break; }
+                Err(now_head_ptr) => { head_ptr = now_head_ptr; }
+            }
+        }
+    }
+    // detach the whole list, the caller owns every node and accounts for them in count.
+    fn take(&self) -> *mut Retired {
+        self.head.swap(std::ptr::null_mut(), Ordering::SeqCst)
+    }
+    // detach the whole list and return its (head, tail, n), or None when empty.
+    fn take_all(&self) -> Option<(*mut Retired, *mut Retired, usize)> {
+        let head_ptr = self.take();
+        if head_ptr.is_null() {
+            return None;
+        }
+        let mut tail_ptr = head_ptr;
+        let mut n = 1;
+        loop {
+            let next_ptr = unsafe { &*tail_ptr }.next.load(Ordering::SeqCst);
+            if next_ptr.is_null() {
+                break;
+            }
+            tail_ptr = next_ptr;
+            n += 1;
+        }
+        self.count.fetch_sub(n, Ordering::SeqCst);
+        Some((head_ptr, tail_ptr, n))
+    }
 }
 
 // Reader stores loaded AtomicPtr *mut T into HazPtr, reclaim until all readers drops ref to ptr.
@@ -165,6 +240,41 @@ impl HazPtr {
         // stashing user's loaded AtomicPtr into HazPtr for later mem reclaim. 
         self.ptr.store(user_t_ptr, Ordering::SeqCst);
     }
+    // protect the ptr loaded from atomic_ptr, re-load until the AtomicPtr did not
+    // change under us, so the returned ptr was not yet retired when protected.
+    // asymmetric: only a compiler fence between store and re-load, bulk_reclaim pays membarrier.
+    fn protect_load<T>(&self, atomic_ptr: &AtomicPtr<T>, asymmetric: bool) -> *mut T {
+        self.protect_load_with(|order| atomic_ptr.load(order), |p| p as *mut u8, asymmetric)
+    }
+    // protect_load for a ptr kept in some other word, e.g. a tagged usize.
+    // ptr_of maps the loaded word to the ptr to protect.
+    fn protect_load_with<W: Copy + Eq>(
+        &self,
+        load: impl Fn(Ordering) -> W,
+        ptr_of: impl Fn(W) -> *mut u8,
+        asymmetric: bool,
+    ) -> W {
+        let reload = if asymmetric { Ordering::Acquire } else { Ordering::SeqCst };
+        let mut word = load(reload);
+        loop {
+            if asymmetric {
+                self.ptr.store(ptr_of(word), Ordering::Relaxed);
+                asymmetric_fence::light();
+            } else {
+                self.protect(ptr_of(word)); // store atomic_ptr
+                // loom takes SeqCst loads and stores for AcqRel and lets the re-load pass the
+                // store, only an SC fence orders them there. Real SeqCst accesses need none.
+                if cfg!(all(test, loom)) {
+                    sync::fence(Ordering::SeqCst);
+                }
+            }
+            let word_now = load(reload);
+            if word == word_now {
+                break word; // no change of user data atomicptr
+            }
+            word = word_now; // assign the latest ptr for hazptr to protect.
+        }
+    }
 }
 // linked list head bucket or tree root of the collection.
 pub struct HazPtrs {
@@ -174,10 +284,225 @@ pub struct HazPtrs {
 // Domain contain active HazPtrs and Retired user_T_ptrs.
 pub struct HazPtrDomain<F> {
     hazptrs: HazPtrs,
-    retired: RetiredList,
+    // writers retire into their thread's shard, bulk_reclaim collects all of them.
+    retired: [RetiredList; MAX_RETIRED_SHARDS],
+    retired_shards: usize,
+    reclaim_threshold: usize,
+    fence: FenceMode,
     family: PhantomData<F>, // compiler pls treats HazPtrDomain has this type.
 }
-static SHARED_DOMAIN: HazPtrDomain<Global> = HazPtrDomain::new(&Global::new());
+// SeqCst, every retire into the global domain may bulk_reclaim and an asymmetric domain pays
+// a membarrier syscall for each. Opt in per domain with DomainConfig::fence.
+const_static! {
+    static SHARED_DOMAIN: HazPtrDomain<Global> = HazPtrDomain::new(&Global::new());
+}
+
+// how a reader orders its hazptr store before re-loading the AtomicPtr, against bulk_reclaim
+// reading the hazptrs after taking the retired list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenceMode {
+    // full fence on both sides: every protect pays for it.
+    SeqCst,
+    // compiler fence on protect, process-wide membarrier on bulk_reclaim.
+    // falls back to SeqCst when membarrier is not available.
+    Asymmetric,
+}
+pub const MAX_RETIRED_SHARDS: usize = 16;
+// folly's kThreshold: a retire only starts bulk_reclaim once its shard holds this many nodes.
+pub const RECLAIM_THRESHOLD: usize = 1000;
+
+// const builder, so static domains can be configured too.
+#[derive(Clone, Copy, Debug)]
+pub struct DomainConfig {
+    fence: FenceMode,
+    retired_shards: usize,
+    reclaim_threshold: usize,
+}
+impl DomainConfig {
+    pub const fn new() -> Self {
+        Self {
+            fence: FenceMode::SeqCst,
+            retired_shards: 1,
+            reclaim_threshold: RECLAIM_THRESHOLD,
+        }
+    }
+    pub const fn fence(mut self, fence: FenceMode) -> Self {
+        self.fence = fence;
+        self
+    }
+    // more shards spread concurrent retire CASes, at the cost of bulk_reclaim taking each one.
+    pub const fn retired_shards(mut self, shards: usize) -> Self {
+        assert!(shards >= 1 && shards <= MAX_RETIRED_SHARDS);
+        self.retired_shards = shards;
+        self
+    }
+    // every bulk_reclaim snapshots all hazptrs, a low threshold trades memory for that walk.
+    // 1 reclaims on every retire. Each shard may hold up to threshold - 1 nodes in between.
+    pub const fn reclaim_threshold(mut self, threshold: usize) -> Self {
+        assert!(threshold >= 1);
+        self.reclaim_threshold = threshold;
+        self
+    }
+}
+impl Default for DomainConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// light() is the reader half, heavy() the reclaimer half: heavy() makes every thread's
+// prior hazptr store visible, as if each of them had run a full fence at light().
+mod asymmetric_fence {
+    use super::sync::{fence, Ordering};
+    use std::sync::atomic::compiler_fence;
+
+    pub(crate) fn light() {
+        compiler_fence(Ordering::SeqCst);
+    }
+    pub(crate) fn heavy(asymmetric: bool) {
+        if asymmetric {
+            super::membarrier::barrier();
+        } else {
+            fence(Ordering::SeqCst);
+        }
+    }
+}
+
+// loom models neither a syscall nor a compiler fence, asymmetric domains fall back to SeqCst.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64"),
+    not(miri),
+    not(loom)
+))]
+mod membarrier {
+    use std::ffi::{c_int, c_long};
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_MEMBARRIER: c_long = 324;
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+    const SYS_MEMBARRIER: c_long = 283;
+    const MEMBARRIER_CMD_PRIVATE_EXPEDITED: c_int = 1 << 3;
+    const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: c_int = 1 << 4;
+
+    const UNKNOWN: u8 = 0;
+    const SUPPORTED: u8 = 1;
+    const UNSUPPORTED: u8 = 2;
+    static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    extern "C" {
+        fn syscall(num: c_long, ...) -> c_long;
+    }
+
+    // registering is idempotent, racing first callers all get the same answer.
+    pub(crate) fn is_supported() -> bool {
+        match STATE.load(Ordering::Relaxed) {
+            SUPPORTED => true,
+            UNSUPPORTED => false,
+            _ => {
+                let registered = unsafe {
+                    syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED, 0 as c_int)
+                } == 0;
+                let state = if registered { SUPPORTED } else { UNSUPPORTED };
+                STATE.store(state, Ordering::Relaxed);
+                registered
+            }
+        }
+    }
+    // registered processes should never see this fail. If it does anyway, stop using it: later
+    // protects see is_supported() false and pay the full fence themselves.
+    pub(crate) fn barrier() {
+        let ret = unsafe { syscall(SYS_MEMBARRIER, MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0 as c_int) };
+        if ret != 0 {
+            STATE.store(UNSUPPORTED, Ordering::Relaxed);
+            std::sync::atomic::fence(Ordering::SeqCst);
+        }
+    }
+}
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64"),
+    not(miri),
+    not(loom)
+)))]
+mod membarrier {
+    pub(crate) fn is_supported() -> bool {
+        false
+    }
+    pub(crate) fn barrier() {
+        unreachable!("membarrier is not supported");
+    }
+}
+
+// Two domains of the same family F type check fine, e.g. HazPtrDomain::new(&()) twice.
+// debug builds always compare the object's domain against the holder's or retirer's domain,
+// release builds opt in with `--cfg hazptr_check_domain`.
+const CHECK_DOMAIN: bool = cfg!(any(debug_assertions, hazptr_check_domain));
+// Per-thread stash of hazptrs released by global domain holders, so HazPtrHolder::global()
+// and load pops one in O(1) instead of walking the global list. Stashed entries stay active,
+// no other thread grabs them. Only the global domain: it is never dropped, its hazptrs are 'static.
+// Except under loom, which drops it at the end of every execution before the main thread's
+// thread-locals, so no cache there.
+const USE_HAZPTR_CACHE: bool = !cfg!(all(test, loom));
+const HAZPTR_CACHE_SIZE: usize = 8;
+struct HazPtrCache {
+    hazptrs: Vec<&'static HazPtr>,
+}
+// thread exit, hand the stashed entries back to the global list.
+impl Drop for HazPtrCache {
+    fn drop(&mut self) {
+        for hazptr in self.hazptrs.drain(..) {
+            hazptr.active.store(false, Ordering::SeqCst);
+        }
+    }
+}
+thread_local! {
+    static HAZPTR_CACHE: RefCell<HazPtrCache> = RefCell::new(HazPtrCache {
+        hazptrs: Vec::with_capacity(HAZPTR_CACHE_SIZE),
+    });
+}
+
+// bulk_reclaim gathers protected ptrs into this thread's buffer, re-used across scans.
+// taken out while in use, a deleter that retires and reclaims again gets a fresh one.
+thread_local! {
+    static HAZARD_BUF: Cell<Vec<*mut u8>> = const { Cell::new(Vec::new()) };
+}
+// up to this many protected ptrs a linear scan beats sort + binary search, see bench_hazard_set.
+// sorting also needs enough retired ptrs to look up to pay for itself.
+const LINEAR_SCAN_MAX: usize = 8;
+
+// protected ptrs of one bulk_reclaim scan.
+struct HazardSet {
+    ptrs: Vec<*mut u8>,
+    sorted: bool,
+}
+impl HazardSet {
+    fn new(mut ptrs: Vec<*mut u8>, lookups: usize, linear_scan_max: usize) -> Self {
+        let sorted = ptrs.len() > linear_scan_max && lookups > ptrs.len().ilog2() as usize;
+        if sorted {
+            ptrs.sort_unstable();
+            ptrs.dedup();
+        }
+        Self { ptrs, sorted }
+    }
+    fn contains(&self, ptr: *mut u8) -> bool {
+        if self.sorted {
+            self.ptrs.binary_search(&ptr).is_ok()
+        } else {
+            self.ptrs.contains(&ptr)
+        }
+    }
+}
+
+// each thread retires into a fixed shard, threads are spread round robin.
+const_static! {
+    static NEXT_RETIRED_SHARD: AtomicUsize = AtomicUsize::new(0);
+}
+thread_local! {
+    static RETIRED_SHARD: usize = NEXT_RETIRED_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
 // specialization of Global domain.
 impl HazPtrDomain<Global> {
     pub fn global() -> &'static Self {
@@ -185,199 +510,347 @@ impl HazPtrDomain<Global> {
     }
 }
 impl<F> HazPtrDomain<F> {
-    pub const fn new(_: &F) -> Self {
-        // const fn can only take ref to avoid call destructors.
-        Self {
-            hazptrs: HazPtrs {
-                // Note AtomicPtr::new takes mut raw ptr(*mut T)
-                head: AtomicPtr::new(std::ptr::null_mut()),
-            },
-            retired: RetiredList {
-                head: AtomicPtr::new(std::ptr::null_mut()),
-                count: AtomicUsize::new(0),
-            },
-            family: PhantomData,
+    loom_const_fn! {
+        pub fn new(family: &F) -> Self {
+            HazPtrDomain::with_config(family, DomainConfig::new())
+        }
+    }
+    loom_const_fn! {
+        pub fn with_config(_: &F, config: DomainConfig) -> Self {
+            // const fn can only take ref to avoid call destructors.
+            Self {
+                hazptrs: HazPtrs {
+                    // Note AtomicPtr::new takes mut raw ptr(*mut T)
+                    head: AtomicPtr::new(std::ptr::null_mut()),
+                },
+                #[cfg(not(all(test, loom)))]
+                retired: [const { RetiredList::new() }; MAX_RETIRED_SHARDS],
+                #[cfg(all(test, loom))]
+                retired: std::array::from_fn(|_| RetiredList::new()),
+                retired_shards: config.retired_shards,
+                reclaim_threshold: config.reclaim_threshold,
+                fence: config.fence,
+                family: PhantomData,
+            }
         }
     }
     // Safety: HazPtrs are never de-allocated.
     // return the shared ref to the hazptr in the hazptrs list in domain.
     // cas set active flag and other fields in HazPtr are all atomic values, hence &HazPtr.
-    pub fn acquire(&self) -> &HazPtr {
+    // Users get hazptrs through HazPtrHolder and HazPtrArray only, their Drop releases them.
+    pub(crate) fn acquire(&self) -> &HazPtr {
+        if USE_HAZPTR_CACHE && self.is_global() {
+            // try_with fails once the thread-local is destroyed at thread exit, walk the list then.
+            let cached = HAZPTR_CACHE.try_with(|cache| cache.borrow_mut().hazptrs.pop());
+            if let Ok(Some(hazptr)) = cached {
+                return hazptr;
+            }
+        }
+        let [hazptr] = self.acquire_many::<1>();
+        hazptr
+    }
+    fn is_global(&self) -> bool {
+        std::ptr::eq(
+            self as *const Self as *const u8,
+            HazPtrDomain::<Global>::global() as *const HazPtrDomain<Global> as *const u8,
+        )
+    }
+    // walk the hazptrs list once and claim up to N inactive entries.
+    // the missing ones are allocated as a private chain, stick in front of head with one CAS.
+    pub(crate) fn acquire_many<const N: usize>(&self) -> [&HazPtr; N] {
+        let mut acquired: [Option<&HazPtr>; N] = [None; N];
+        let mut n = 0;
         let head = &self.hazptrs.head;
         let mut cur_ptr = head.load(Ordering::SeqCst); // using shared ref atomicptr to load raw ptr, *mut T,
-        loop {
-            // following head next to find an non-active entry
-            while !cur_ptr.is_null() && unsafe { &*cur_ptr }.active.load(Ordering::SeqCst) {
-                cur_ptr = unsafe { &*cur_ptr }.next.load(Ordering::SeqCst);
-            }
-            if cur_ptr.is_null() {
-                // No free HazPtrs -- need to allocate a new one
+        while n < N && !cur_ptr.is_null() {
+            let cur = unsafe { &*cur_ptr };
+            if !cur.active.load(Ordering::SeqCst)
+                && cur
+                    .active
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                acquired[n] = Some(cur); // It's ours!
+                n += 1;
+            } // otherwise someone else grabbed this node racing, keep walking.
+            cur_ptr = cur.next.load(Ordering::SeqCst);
+        }
+        if n < N {
+            // No free HazPtrs -- allocate the missing ones, each new one links to the previous.
+            let mut chain_head: *mut HazPtr = std::ptr::null_mut();
+            let mut chain_tail: *mut HazPtr = std::ptr::null_mut();
+            while n < N {
                 let new_hazptr = Box::into_raw(Box::new(HazPtr {
                     ptr: AtomicPtr::new(std::ptr::null_mut()),
-                    next: AtomicPtr::new(std::ptr::null_mut()),
+                    next: AtomicPtr::new(chain_head),
                     active: AtomicBool::new(true),
                 }));
-                break loop {
-                    // only use *get_mut() to update next() from null_mut to own old_head.
-                    // otherwise, the existing *T in the AtomicPtr<T> will be leaking. 
-                    // stick newly allocated as new head.
-                    let mut old_head_ptr = head.load(Ordering::SeqCst);
-                    *unsafe { &mut *new_hazptr }.next.get_mut() = old_head_ptr;
-                    match head.compare_exchange_weak(
-                        old_head_ptr,
-                        new_hazptr,
-                        Ordering::SeqCst,
-                        Ordering::SeqCst,
-                    ) {
-                        Ok(_) => { break unsafe { &*new_hazptr }; }
-                        Err(head_now) => { old_head_ptr = head_now; }
-                    }
-                };
-            } else {
-                let cur = unsafe { &*cur_ptr };
-                if cur
-                    .active
-                    .compare_exchange_weak(false, true, Ordering::SeqCst, Ordering::SeqCst)
-                    .is_ok()
-                {
-                    break cur;
-                } // It's ours!
-                  // keep walking as someone else grabbed this node racing.
+                if chain_tail.is_null() {
+                    chain_tail = new_hazptr;
+                }
+                chain_head = new_hazptr;
+                acquired[n] = Some(unsafe { &*new_hazptr });
+                n += 1;
+            }
+            // shared refs to the chain are handed out already, set tail next by store, not get_mut().
+            let chain_tail = unsafe { &*chain_tail };
+            let mut old_head_ptr = head.load(Ordering::SeqCst);
+            loop {
+                chain_tail.next.store(old_head_ptr, Ordering::SeqCst);
+                match head.compare_exchange_weak(
+                    old_head_ptr,
+                    chain_head,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                ) {
+                    Ok(_) => { break; }
+                    Err(head_now) => { old_head_ptr = head_now; }
+                }
             }
         }
+        acquired.map(|hazptr| hazptr.expect("acquired N hazptrs"))
     }
-    pub(crate) unsafe fn retire<'domain>(
-        &'domain self,  
-        user_t_ptr: *mut (dyn Reclaim + 'domain),  // raw mut user_t_ptr to HazPtrObj<T> from Box::into_raw to retire
-        // deleter: &'static dyn Deleter,
-        deleter: fn(*mut dyn Reclaim),
-    ) {
-        // Box a Retired::new and link it to domain retired linked list AtomicPtr<Retired>.
-        let newly_retired = Box::into_raw(Box::new(unsafe { Retired::new(self, user_t_ptr, deleter) }));
-        self.retired.count.fetch_add(1, Ordering::SeqCst);
-        let mut retired_head_ptr = self.retired.head.load(Ordering::SeqCst);
-        loop {
-            // *unsafe { &mut *newly_retired }.next.get_mut() = retired_head_ptr;
-            unsafe {&mut *newly_retired }.next.store(retired_head_ptr, Ordering::SeqCst);
-            match self.retired.head.compare_exchange_weak(
-                retired_head_ptr,
-                newly_retired,
-                Ordering::SeqCst,
-                Ordering::SeqCst,
-            ) {
-                Ok(_) => {
-                    break;
-                }
-                Err(now_head_ptr) => {
-                    retired_head_ptr = now_head_ptr;
+    // reset the protected ptr and de-active the entry so the next acquire can reuse it.
+    // global domain entries are stashed active in the thread cache while it has room.
+    pub(crate) fn release(&self, hazptr: &HazPtr) {
+        hazptr.ptr.store(std::ptr::null_mut(), Ordering::SeqCst);
+        if USE_HAZPTR_CACHE && self.is_global() {
+            // Safety: the global domain is a static, its hazptrs are never de-allocated.
+            let hazptr: &'static HazPtr = unsafe { &*(hazptr as *const HazPtr) };
+            let stashed = HAZPTR_CACHE.try_with(|cache| {
+                let mut cache = cache.borrow_mut();
+                if cache.hazptrs.len() < HAZPTR_CACHE_SIZE {
+                    cache.hazptrs.push(hazptr);
+                    true
+                } else {
+                    false
                 }
+            });
+            if let Ok(true) = stashed {
+                return;
             }
         }
-        if self.retired.count.load(Ordering::SeqCst) != 0 {
+        hazptr.active.store(false, Ordering::SeqCst);
+    }
+    // panic when an object of domain `other` is used with this domain.
+    fn check_domain(&self, other: &HazPtrDomain<F>, op: &str) {
+        if CHECK_DOMAIN && !std::ptr::eq(self, other) {
+            panic!(
+                "{}: object belongs to HazPtrDomain {:p}, not to this HazPtrDomain {:p}",
+                op, other, self
+            );
+        }
+    }
+    pub(crate) unsafe fn retire<'domain, T>(
+        &'domain self,
+        user_t_ptr: *mut T, // raw mut user_t_ptr to HazPtrObj<T> from Box::into_raw to retire
+        deleter: fn(*mut dyn Reclaim),
+    ) where
+        T: HazPtrObject<'domain, F>,
+        F: 'static,
+    {
+        unsafe { self.retire_deleter(user_t_ptr, RetiredDeleter::Fn(deleter)) }
+    }
+    pub(crate) unsafe fn retire_with<'domain, T>(
+        &'domain self,
+        user_t_ptr: *mut T,
+        deleter: &'static dyn Deleter,
+    ) where
+        T: HazPtrObject<'domain, F>,
+        F: 'static,
+    {
+        unsafe { self.retire_deleter(user_t_ptr, RetiredDeleter::Dyn(deleter)) }
+    }
+    unsafe fn retire_deleter<'domain, T>(&'domain self, user_t_ptr: *mut T, deleter: RetiredDeleter)
+    where
+        T: HazPtrObject<'domain, F>,
+        F: 'static,
+    {
+        // retiring into another domain means its readers never see our hazptrs.
+        self.check_domain(unsafe { &*user_t_ptr }.domain(), "retire");
+        let user_t_ptr = user_t_ptr as *mut (dyn Reclaim + 'domain);
+        // Box a Retired::new and link it to domain retired linked list AtomicPtr<Retired>.
+        let newly_retired = Box::into_raw(Box::new(Retired::new(self, user_t_ptr, deleter)));
+        let shard = self.retired_shard();
+        shard.push(newly_retired, newly_retired, 1);
+        if shard.count.load(Ordering::SeqCst) >= self.reclaim_threshold {
             self.bulk_reclaim(0, false);
         }
     }
+    fn retired_shard(&self) -> &RetiredList {
+        // thread-local gone at thread exit, any shard is fine.
+        let shard = RETIRED_SHARD.try_with(|shard| *shard).unwrap_or(0);
+        &self.retired[shard % self.retired_shards]
+    }
     pub fn eager_reclaim(&self, block: bool) -> usize {
         self.bulk_reclaim(0, block)
     }
-    fn active_hazptrs(&self) -> HashSet<*mut u8> {
-        let mut active_ptrs = HashSet::new();
+    // readers of an asymmetric domain skip the full fence, fall back when we can not pay for it.
+    fn asymmetric(&self) -> bool {
+        self.fence == FenceMode::Asymmetric && membarrier::is_supported()
+    }
+    // called after the retired list was taken, the heavy fence pairs with readers' protect.
+    fn active_hazptrs(&self, mut active_ptrs: Vec<*mut u8>, lookups: usize) -> HazardSet {
+        asymmetric_fence::heavy(self.asymmetric());
+        active_ptrs.clear();
         // start with cur <= head; while !cur.is_null(); fn(cur); cur=cur.next;
-        let mut hazptr_ptr = self.hazptrs.head.load(Ordering::SeqCst);
+        let mut hazptr_ptr = self.hazptrs.head.load(Ordering::Acquire);
         while !hazptr_ptr.is_null() {
             let hazptr = unsafe { &*hazptr_ptr };
-            if hazptr.active.load(Ordering::SeqCst) {
-                active_ptrs.insert(hazptr.ptr.load(Ordering::SeqCst));  // ptr value(u8) to T into hashset.
+            if hazptr.active.load(Ordering::Acquire) {
+                let ptr = hazptr.ptr.load(Ordering::Acquire); // ptr value(u8) to T.
+                if !ptr.is_null() {
+                    active_ptrs.push(ptr);
+                }
             }
-            hazptr_ptr = hazptr.next.load(Ordering::SeqCst);
+            hazptr_ptr = hazptr.next.load(Ordering::Acquire);
         }
-        active_ptrs
+        HazardSet::new(active_ptrs, lookups, LINEAR_SCAN_MAX)
     }
-    fn bulk_reclaim(&self, prev_reclaimed: usize, block: bool) -> usize {
-        let retired_list_head = self
-            .retired
-            .head
-            .swap(std::ptr::null_mut(), Ordering::SeqCst);
+    // take every shard, so concurrent bulk_reclaims each own a disjoint set of Retired nodes.
+    fn bulk_reclaim(&self, prev_reclaimed: usize, _block: bool) -> usize {
+        // chain all shards into one private list.
+        let mut retired_list_head: *mut Retired = std::ptr::null_mut();
+        let mut total_taken = 0;
+        for shard in &self.retired[..self.retired_shards] {
+            if let Some((head_ptr, tail_ptr, taken)) = shard.take_all() {
+                unsafe { &*tail_ptr }.next.store(retired_list_head, Ordering::SeqCst);
+                retired_list_head = head_ptr;
+                total_taken += taken;
+            }
+        }
         if retired_list_head.is_null() {
             return 0;
         }
-
-        let active_ptrs = self.active_hazptrs();
+        // still guarded ones go back into our own shard.
+        let reclaimed = self.reclaim_unguarded(retired_list_head, total_taken, self.retired_shard());
+        prev_reclaimed + reclaimed
+    }
+    // run the deleter of every node of a private list of n Retired nodes that no hazptr
+    // protects, stick the still guarded ones back onto `guarded_into`.
+    fn reclaim_unguarded(
+        &self,
+        retired_list_head: *mut Retired,
+        n: usize,
+        guarded_into: &RetiredList,
+    ) -> usize {
+        let hazard_buf = HAZARD_BUF.try_with(Cell::take).unwrap_or_default();
+        let active_ptrs = self.active_hazptrs(hazard_buf, n);
 
         // Reclaim any retired objects that aren't guarded
         let mut still_guarded_head_ptr: *mut Retired = std::ptr::null_mut(); //
         let mut tail = None;
+        let mut still_guarded: usize = 0;
         let mut reclaimed: usize = 0;
         // walk the retired list
         let mut cur_ptr = retired_list_head;
         while !cur_ptr.is_null() {
             let cur_retired = unsafe { &*cur_ptr };  // cast *mut T to &T. 
-            let mut next_retired_ptr = cur_retired.next.load(Ordering::SeqCst);
-            if active_ptrs.contains(&(cur_retired.ptr as *mut u8)) {
+            let next_retired_ptr = cur_retired.next.load(Ordering::SeqCst);
+            if active_ptrs.contains(cur_retired.ptr as *mut u8) {
                 // still guarded, not safe to reclaim, insert as the head of still_guarded_list
                 cur_retired
                     .next
                     .store(still_guarded_head_ptr, Ordering::SeqCst);
                 still_guarded_head_ptr = cur_ptr; // *mut T is plain-old-obj, no move
+                still_guarded += 1;
                 if tail.is_none() {
                     tail = Some(still_guarded_head_ptr);
                 }
             } else {
                 // Retired is in heap by Box::new(), back to box to own the ptr, and delete the box also.
                 let boxed_retired_node = unsafe { Box::from_raw(cur_ptr) }; // box own the cur_ptr
-                // unsafe { boxed_retired_node.deleter.delete(boxed_retired_node.ptr) };
-                unsafe { (boxed_retired_node.deleter)(boxed_retired_node.ptr) };
+                unsafe { boxed_retired_node.deleter.delete(boxed_retired_node.ptr) };
                 reclaimed += 1;
                 // drop the retired box that owns the ptr
             }
             cur_ptr = next_retired_ptr;
         }
 
-        self.retired.count.fetch_sub(reclaimed, Ordering::SeqCst);
-        let total_reclaimed = prev_reclaimed + reclaimed;
+        let _ = HAZARD_BUF.try_with(|buf| buf.set(active_ptrs.ptrs));
 
         // stick back still_guarded list
-        let tail_ptr = if let Some(tail_ptr) = tail {
+        if let Some(tail_ptr) = tail {
             assert!(!still_guarded_head_ptr.is_null());
-            tail_ptr
-        } else {
-            return total_reclaimed;
-        };
+            guarded_into.push(still_guarded_head_ptr, tail_ptr, still_guarded);
+        }
+        reclaimed
+    }
+    // run the deleter of every node of a private list of n Retired nodes, or of none of them
+    // while a hazptr protects any one. Then the list goes back onto `guarded_into` whole.
+    fn reclaim_all_or_none(
+        &self,
+        retired_list_head: *mut Retired,
+        retired_list_tail: *mut Retired,
+        n: usize,
+        guarded_into: &RetiredList,
+    ) -> usize {
+        let hazard_buf = HAZARD_BUF.try_with(Cell::take).unwrap_or_default();
+        let active_ptrs = self.active_hazptrs(hazard_buf, n);
+        let mut guarded = false;
+        let mut cur_ptr = retired_list_head;
+        while !cur_ptr.is_null() && !guarded {
+            let cur_retired = unsafe { &*cur_ptr };
+            guarded = active_ptrs.contains(cur_retired.ptr as *mut u8);
+            cur_ptr = cur_retired.next.load(Ordering::SeqCst);
+        }
+        let _ = HAZARD_BUF.try_with(|buf| buf.set(active_ptrs.ptrs));
+        if guarded {
+            guarded_into.push(retired_list_head, retired_list_tail, n);
+            return 0;
+        }
 
-        let mut retired_list_head_ptr = self.retired.head.load(Ordering::SeqCst);
-        let still_guarded_tail = unsafe { &mut *tail_ptr };
-        loop {
-            *unsafe { still_guarded_tail.next.get_mut() } = retired_list_head_ptr;
-            match self.retired.head.compare_exchange_weak(
-                retired_list_head_ptr,
-                still_guarded_head_ptr,
-                Ordering::SeqCst,
-                Ordering::SeqCst,
-            ) {
-                Ok(_) => {
-                    break;
-                }
-                Err(now_retired_list_head_ptr) => {
-                    retired_list_head_ptr = now_retired_list_head_ptr;
-                }
-            }
+        // unlinked and unguarded, no reader can protect any of them any more.
+        let mut cur_ptr = retired_list_head;
+        while !cur_ptr.is_null() {
+            let boxed_retired_node = unsafe { Box::from_raw(cur_ptr) };
+            cur_ptr = boxed_retired_node.next.load(Ordering::SeqCst);
+            unsafe { boxed_retired_node.deleter.delete(boxed_retired_node.ptr) };
         }
-        total_reclaimed
+        n
     }
 }
 
+// &mut self means no holder borrows the domain any more. An active hazptr here is
+// a leaked holder (mem::forget), its protected ptr may still be in use, bail out.
 impl<F> Drop for HazPtrDomain<F> {
     fn drop(&mut self) {
-        let nretired = *self.retired.count.get_mut();
-        let nreclaimed = self.bulk_reclaim(0, false);
-        assert_eq!(nretired, nreclaimed);
-        assert!(self.retired.head.get_mut().is_null());
-        // drop all hazptrs
-        let mut hazptr_ptr: *mut HazPtr = *self.hazptrs.head.get_mut();
+        let mut hazptr_ptr: *mut HazPtr = self.hazptrs.head.with_mut(|head| *head);
+        while !hazptr_ptr.is_null() {
+            let hazptr = unsafe { &mut *hazptr_ptr };
+            // &mut, relaxed is as good as get_mut, which loom's AtomicBool does not have.
+            assert!(
+                !hazptr.active.load(Ordering::Relaxed),
+                "HazPtrDomain dropped while a HazPtrHolder is still active"
+            );
+            hazptr_ptr = hazptr.next.with_mut(|next| *next);
+        }
+
+        // no readers left, run every deleter regardless of the hazptr snapshot.
+        // a deleter may retire more objects into this domain, keep draining until empty.
+        let mut drained = false;
+        while !drained {
+            drained = true;
+            for shard in &self.retired {
+                let mut cur_ptr = shard.take();
+                while !cur_ptr.is_null() {
+                    drained = false;
+                    let boxed_retired_node = unsafe { Box::from_raw(cur_ptr) };
+                    cur_ptr = boxed_retired_node.next.load(Ordering::SeqCst);
+                    unsafe { boxed_retired_node.deleter.delete(boxed_retired_node.ptr) };
+                    shard.count.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        }
+        for shard in &mut self.retired {
+            assert_eq!(shard.count.with_mut(|count| *count), 0);
+        }
+
+        // free all hazptr nodes in the list.
+        let mut hazptr_ptr: *mut HazPtr = self.hazptrs.head.with_mut(|head| *head);
         while !hazptr_ptr.is_null() {
             let mut hazptr: Box<HazPtr> = unsafe { Box::from_raw(hazptr_ptr) };
-            assert!(*hazptr.active.get_mut());
-            hazptr_ptr = *hazptr.next.get_mut();
+            hazptr_ptr = hazptr.next.with_mut(|next| *next);
             drop(hazptr);
         }
     }
@@ -394,13 +867,27 @@ where
     Self: Sized + 'domain,
 {
     fn domain(&self) -> &'domain HazPtrDomain<F>;
-    // deleter is dyn dispatch trait pointer.
-    // unsafe fn retire(&mut self, deleter: &'static dyn Deleter) {
+    /// deleter is a plain fn pointer, e.g. deleters::_drop_box.
+    ///
+    /// # Safety
+    ///
+    /// self is unlinked, no new reader can load it, and it is retired only once. deleter
+    /// frees it the way it was allocated, e.g. _drop_box for a Box::into_raw ptr.
     unsafe fn retire(&mut self, deleter: fn(*mut dyn Reclaim)) {
-        // cast HazPtrObject to dyn Reclaim ptr
-        let ptr = self as *mut (dyn Reclaim + 'domain);
+        let ptr = self as *mut Self;
+        unsafe {
+            self.domain().retire(ptr, deleter);
+        }
+    }
+    /// deleter is dyn dispatch trait pointer, e.g. &deleters::drop_box.
+    ///
+    /// # Safety
+    ///
+    /// Same as retire.
+    unsafe fn retire_with(&mut self, deleter: &'static dyn Deleter) {
+        let ptr = self as *mut Self;
         unsafe {
-            (&*self).domain().retire(ptr, deleter);
+            self.domain().retire_with(ptr, deleter);
         }
     }
 }
@@ -442,6 +929,126 @@ impl<T, F> DerefMut for HazPtrObjectWrapper<'_, T, F> {
     }
 }
 
+// AtomicPtr<HazPtrObjectWrapper<T>> that always owns a Box::into_raw of its wrapper.
+// Writers box the new T, swap it in and retire the replaced box into the domain,
+// so neither readers nor writers need unsafe. Never null.
+pub struct HazAtomicPtr<'domain, T: 'domain, F: 'static> {
+    ptr: AtomicPtr<HazPtrObjectWrapper<'domain, T, F>>,
+    domain: &'domain HazPtrDomain<F>,
+}
+// AtomicPtr alone would make any T shareable. Readers on other threads get &T, writers
+// and reclaim on other threads move and drop T.
+// Send needs T: Sync as well: a loaded &T lives as long as the holder, not the borrow of
+// the HazAtomicPtr, so it can be moved to another thread and loaded there too.
+/// ```compile_fail
+/// use lockfree::haphazard_domain::{HazAtomicPtr, HazPtrHolder};
+/// use std::cell::Cell;
+///
+/// let ptr = HazAtomicPtr::new(Cell::new(0));
+/// let mut holder = HazPtrHolder::global();
+/// let cell = ptr.load(&mut holder);
+/// let t = std::thread::spawn(move || {
+///     let mut holder = HazPtrHolder::global();
+///     ptr.load(&mut holder).set(1);
+/// });
+/// cell.set(2); // races with the set(1) above
+/// t.join().unwrap();
+/// ```
+unsafe impl<'domain, T: Send + Sync + 'domain, F: Sync + 'static> Send
+    for HazAtomicPtr<'domain, T, F>
+{
+}
+unsafe impl<'domain, T: Send + Sync + 'domain, F: Sync + 'static> Sync
+    for HazAtomicPtr<'domain, T, F>
+{
+}
+impl<T> HazAtomicPtr<'static, T, Global> {
+    pub fn new(t: T) -> Self {
+        HazAtomicPtr::with_domain(HazPtrDomain::global(), t)
+    }
+}
+impl<'domain, T: 'domain, F: 'static> HazAtomicPtr<'domain, T, F> {
+    pub fn with_domain(domain: &'domain HazPtrDomain<F>, t: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+                domain, t,
+            )))),
+            domain,
+        }
+    }
+    fn boxed(&self, t: T) -> *mut HazPtrObjectWrapper<'domain, T, F> {
+        Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(self.domain, t)))
+    }
+    // a hazptr of another domain does not keep our values from reclaim. The api is safe, so
+    // unlike check_domain this is checked in release builds too.
+    fn assert_domain(&self, domain: &HazPtrDomain<F>, op: &str) {
+        assert!(
+            std::ptr::eq(self.domain, domain),
+            "{}: HazPtrDomain {:p} is not the HazAtomicPtr's HazPtrDomain {:p}",
+            op,
+            domain,
+            self.domain
+        );
+    }
+    // the loaded value stays valid until the holder is reset, re-used or dropped.
+    pub fn load<'l>(
+        &self,
+        holder: &'l mut HazPtrHolder<'domain, F>,
+    ) -> &'l HazPtrObjectWrapper<'domain, T, F> {
+        self.assert_domain(holder.domain, "HazAtomicPtr::load");
+        // Safety:
+        //  1. ptr always points to a Box from with_domain or boxed, so is valid.
+        //  2. every replaced value is retired into the domain, never freed directly.
+        unsafe { holder.load(&self.ptr) }.expect("HazAtomicPtr is never null")
+    }
+    // swap in t, the old value is protected by holder and retired.
+    pub fn swap<'l>(
+        &self,
+        t: T,
+        holder: &'l mut HazPtrHolder<'domain, F>,
+    ) -> &'l HazPtrObjectWrapper<'domain, T, F> {
+        self.assert_domain(holder.domain, "HazAtomicPtr::swap");
+        let old = self.ptr.swap(self.boxed(t), Ordering::SeqCst);
+        // we own old until retire, nobody else can have retired it yet.
+        holder.get_hazptr_from_domain().protect(old as *mut u8);
+        unsafe { self.retire(old) };
+        unsafe { &*old }
+    }
+    pub fn store(&self, t: T) {
+        let old = self.ptr.swap(self.boxed(t), Ordering::SeqCst);
+        unsafe { self.retire(old) };
+    }
+    // current is a value loaded from this HazAtomicPtr. Hand back new if it was replaced meanwhile.
+    pub fn compare_exchange(
+        &self,
+        current: &HazPtrObjectWrapper<'domain, T, F>,
+        new: T,
+    ) -> Result<(), T> {
+        self.assert_domain(current.domain, "HazAtomicPtr::compare_exchange");
+        let current = current as *const HazPtrObjectWrapper<'domain, T, F> as *mut _;
+        let new = self.boxed(new);
+        match self.ptr.compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(old) => {
+                unsafe { self.retire(old) };
+                Ok(())
+            }
+            // new never got published, take it back.
+            Err(_) => Err(unsafe { Box::from_raw(new) }.inner),
+        }
+    }
+    // Safety: old was swapped out of self.ptr by the caller, so is retired only once.
+    unsafe fn retire(&self, old: *mut HazPtrObjectWrapper<'domain, T, F>) {
+        unsafe { { &mut *old }.retire(deleters::_drop_box) };
+    }
+}
+// readers may still hold the current value through their holder, retire instead of free.
+impl<'domain, T: 'domain, F: 'static> Drop for HazAtomicPtr<'domain, T, F> {
+    fn drop(&mut self) {
+        let cur = self.ptr.with_mut(|cur| *cur);
+        unsafe { self.retire(cur) };
+    }
+}
+
 //
 // Reader thread uses HazPtrHolder load to read AtomicPtr *mut u8, protect it into HazPtrHolder::hazptr
 // so it can be Gced after drop.
@@ -461,23 +1068,26 @@ impl<T, F> DerefMut for HazPtrObjectWrapper<'_, T, F> {
 // HazPtrHolder::get_hazptr_from_domain() -> HazPtr and 
 // HazPtrHolder::load(ptr) -> HazPtr::protect() return ptr to the original HazPtrObjectWrapper<T>
 // &HazPtrObjectWrapper has Deref to &T.
-struct HazPtrHolder<'domain, F> {
+pub struct HazPtrHolder<'domain, F> {
     hazptr: Option<&'domain HazPtr>,  // a ref to HazPtr in the domain
     domain: &'domain HazPtrDomain<F>, // holder belongs to a domain
 }
 // Obtain a HazPtrHolder, which only has two refs to domain and HazPtr entry in domain.
-impl HazPtrHolder<'static, crate::Global> {
-    fn global() -> Self {
+impl HazPtrHolder<'static, Global> {
+    pub fn global() -> Self {
         HazPtrHolder::for_domain(HazPtrDomain::global())
     }
 }
 impl<'domain, F> HazPtrHolder<'domain, F> {
-    fn for_domain(domain: &'domain HazPtrDomain<F>) -> Self {
+    pub fn for_domain(domain: &'domain HazPtrDomain<F>) -> Self {
         Self {
             hazptr: None,
             domain,
         }
     }
+    pub fn domain(&self) -> &'domain HazPtrDomain<F> {
+        self.domain
+    }
     // get a hazptr entry from domain hazptrs list.
     fn get_hazptr_from_domain(&mut self) -> &'domain HazPtr {
         if let Some(hazptr) = self.hazptr {
@@ -488,8 +1098,13 @@ impl<'domain, F> HazPtrHolder<'domain, F> {
             hazptr
         }
     }
-    // Reader uses HazPtrHolder load(&AtomicPtr<HazPtrObjWrap<T>>) to read *mut u8.
-    // the data ptr behind AtomicPtr is then protected into HazPtrHolder and tracked for reclaim.
+    /// Reader uses HazPtrHolder load(&AtomicPtr<HazPtrObjWrap<T>>) to read *mut u8.
+    /// the data ptr behind AtomicPtr is then protected into HazPtrHolder and tracked for reclaim.
+    ///
+    /// # Safety
+    ///
+    /// atomic_ptr holds null or a live object of this domain, and objects unlinked from it are
+    /// retired, never freed directly.
     pub unsafe fn load<'l, 'o, T>(&'l mut self, atomic_ptr: &'_ AtomicPtr<T>) -> Option<&'l T>
     where
         T: HazPtrObject<'o, F>, // HazPtrObjectWrapper<'o, >
@@ -497,20 +1112,58 @@ impl<'domain, F> HazPtrHolder<'domain, F> {
         F: 'static,
     {
         let hazptr = self.get_hazptr_from_domain();
-        let mut user_t_ptr = atomic_ptr.load(Ordering::SeqCst);
-        loop {
-            hazptr.protect(user_t_ptr as *mut u8); // store atomic_ptr
-            let ptr_now = atomic_ptr.load(Ordering::SeqCst);
-            if user_t_ptr == ptr_now {
-                // no change of user data atomicptr
-                // we can do unsafe { ptr.as_ref() }; but check not null by NonNull
-                break std::ptr::NonNull::new(user_t_ptr).map(|nonnull_ptr| {
-                    unsafe { nonnull_ptr.as_ref() } // map *mut T to &T for return
-                });
-            } else {
-                user_t_ptr = ptr_now; // assign the latest ptr for hazptr to protect.
-            }
+        let user_t_ptr = hazptr.protect_load(atomic_ptr, self.domain.asymmetric());
+        // we can do unsafe { ptr.as_ref() }; but check not null by NonNull
+        std::ptr::NonNull::new(user_t_ptr).map(|nonnull_ptr| {
+            let user_t = unsafe { nonnull_ptr.as_ref() }; // map *mut T to &T for return
+            // our hazptr does not guard an object the writer retires into its own domain.
+            self.domain.check_domain(user_t.domain(), "HazPtrHolder::load");
+            user_t
+        })
+    }
+    // protect an object the caller keeps from reclaim meanwhile, e.g. through another hazptr,
+    // or as a linked node only the caller may unlink. Nothing changes under us, no re-load.
+    //
+    // Safety: user_t_ptr is not null and is not reclaimed before this returns.
+    pub(crate) unsafe fn protect_ptr<'l, 'o, T>(&'l mut self, user_t_ptr: *mut T) -> &'l T
+    where
+        T: HazPtrObject<'o, F>,
+        'o: 'l,
+        F: 'static,
+    {
+        self.get_hazptr_from_domain().protect(user_t_ptr as *mut u8);
+        let user_t = unsafe { &*user_t_ptr };
+        self.domain.check_domain(user_t.domain(), "HazPtrHolder::protect_ptr");
+        user_t
+    }
+    /// load for an object ptr kept in a word with tag bits. ptr_of maps the word to the object,
+    /// null when the word holds none. Returns the word with the object it protects.
+    ///
+    /// # Safety
+    ///
+    /// Same as load, for the ptrs ptr_of returns: each is null or a live object of this
+    /// domain, retired once no longer reachable from word, never freed directly.
+    pub unsafe fn load_word<'l, 'o, T>(
+        &'l mut self,
+        word: &'_ AtomicUsize,
+        ptr_of: impl Fn(usize) -> *mut T,
+    ) -> (usize, Option<&'l T>)
+    where
+        T: HazPtrObject<'o, F>,
+        'o: 'l,
+        F: 'static,
+    {
+        let hazptr = self.get_hazptr_from_domain();
+        let w = hazptr.protect_load_with(
+            |order| word.load(order),
+            |w| ptr_of(w) as *mut u8,
+            self.domain.asymmetric(),
+        );
+        let user_t = unsafe { ptr_of(w).as_ref() };
+        if let Some(user_t) = user_t {
+            self.domain.check_domain(user_t.domain(), "HazPtrHolder::load_word");
         }
+        (w, user_t)
     }
     pub fn reset(&mut self) {
         if let Some(hazptr) = self.hazptr {
@@ -521,16 +1174,168 @@ impl<'domain, F> HazPtrHolder<'domain, F> {
 // Drop a HazPtrHolder never delete the holder, just de-active and reset the hazptr.
 impl<F> Drop for HazPtrHolder<'_, F> {
     fn drop(&mut self) {
-        self.reset();
         if let Some(hazptr) = self.hazptr {
-            hazptr.active.store(false, Ordering::SeqCst);
+            self.domain.release(hazptr);
         }
     }
 }
 
-#[cfg(test)]
+// Retirements grouped under one owner, e.g. every node of an unlinked sublist.
+// Members go to the cohort's own retired list instead of the domain's and are reclaimed as
+// one unit: reclaim frees all of them, or none while a reader protects any member.
+// Like folly's hazptr_obj_cohort, dropping the cohort frees every member before it returns,
+// so the owner holding the cohort outlives all of them.
+pub struct HazPtrCohort<'domain, F> {
+    domain: &'domain HazPtrDomain<F>,
+    retired: RetiredList,
+}
+impl HazPtrCohort<'static, Global> {
+    pub fn global() -> Self {
+        HazPtrCohort::for_domain(HazPtrDomain::global())
+    }
+}
+impl<'domain, F> HazPtrCohort<'domain, F> {
+    pub fn for_domain(domain: &'domain HazPtrDomain<F>) -> Self {
+        Self {
+            domain,
+            retired: RetiredList::new(),
+        }
+    }
+    /// retire user_t_ptr as a member of this cohort.
+    ///
+    /// # Safety
+    ///
+    /// Same as HazPtrObject::retire: user_t_ptr is unlinked, so no new reader can load it,
+    /// and it is retired only once.
+    pub unsafe fn retire<T>(&self, user_t_ptr: *mut T, deleter: fn(*mut dyn Reclaim))
+    where
+        T: HazPtrObject<'domain, F>,
+        F: 'static,
+    {
+        unsafe { self.retire_deleter(user_t_ptr, RetiredDeleter::Fn(deleter)) }
+    }
+    /// retire with a &'static dyn Deleter instead of a fn.
+    ///
+    /// # Safety
+    ///
+    /// Same as HazPtrCohort::retire.
+    pub unsafe fn retire_with<T>(&self, user_t_ptr: *mut T, deleter: &'static dyn Deleter)
+    where
+        T: HazPtrObject<'domain, F>,
+        F: 'static,
+    {
+        unsafe { self.retire_deleter(user_t_ptr, RetiredDeleter::Dyn(deleter)) }
+    }
+    unsafe fn retire_deleter<T>(&self, user_t_ptr: *mut T, deleter: RetiredDeleter)
+    where
+        T: HazPtrObject<'domain, F>,
+        F: 'static,
+    {
+        self.domain
+            .check_domain(unsafe { &*user_t_ptr }.domain(), "HazPtrCohort::retire");
+        let user_t_ptr = user_t_ptr as *mut (dyn Reclaim + 'domain);
+        let newly_retired = Box::into_raw(Box::new(Retired::new(self.domain, user_t_ptr, deleter)));
+        self.retired.push(newly_retired, newly_retired, 1);
+    }
+    // reclaim every member if no reader protects any of them right now, else none.
+    pub fn reclaim(&self) -> usize {
+        match self.retired.take_all() {
+            Some((head_ptr, tail_ptr, n)) => {
+                self.domain.reclaim_all_or_none(head_ptr, tail_ptr, n, &self.retired)
+            }
+            None => 0,
+        }
+    }
+    // members retired and not yet reclaimed.
+    pub fn len(&self) -> usize {
+        self.retired.count.load(Ordering::SeqCst)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+// waits for readers still protecting a member. They only hold a hazptr for the length of a
+// read, and no new reader can protect a member, all of them are unlinked already. The
+// dropping thread itself must not protect one, it would wait forever.
+impl<F> Drop for HazPtrCohort<'_, F> {
+    fn drop(&mut self) {
+        self.reclaim();
+        while !self.is_empty() {
+            thread::yield_now();
+            self.reclaim();
+        }
+    }
+}
+
+// N hazptrs acquired from the domain in one pass, for traversals that protect prev, cur
+// and next at the same time. Slots are Cells so swap() can move a protection to another
+// slot while refs loaded through it are still out: the HazPtr entry keeps guarding the ptr.
+pub struct HazPtrArray<'domain, F, const N: usize> {
+    hazptrs: [Cell<&'domain HazPtr>; N], // refs to N HazPtr entries in the domain
+    domain: &'domain HazPtrDomain<F>,
+}
+impl<const N: usize> HazPtrArray<'static, Global, N> {
+    pub fn global() -> Self {
+        HazPtrArray::for_domain(HazPtrDomain::global())
+    }
+}
+impl<'domain, F, const N: usize> HazPtrArray<'domain, F, N> {
+    pub fn for_domain(domain: &'domain HazPtrDomain<F>) -> Self {
+        Self {
+            hazptrs: domain.acquire_many::<N>().map(Cell::new),
+            domain,
+        }
+    }
+    /// protect the ptr behind atomic_ptr in slot i, same as HazPtrHolder::load.
+    ///
+    /// # Safety
+    ///
+    /// atomic_ptr holds null or a live object of this domain, and objects unlinked from it are
+    /// retired, never freed directly. The returned ref is only valid until slot i is loaded
+    /// again or reset, or until another slot is swapped into slot i and re-used.
+    pub unsafe fn load<'l, 'o, T>(&'l self, i: usize, atomic_ptr: &'_ AtomicPtr<T>) -> Option<&'l T>
+    where
+        T: HazPtrObject<'o, F>,
+        'o: 'l,
+        F: 'static,
+    {
+        let user_t_ptr = self.hazptrs[i]
+            .get()
+            .protect_load(atomic_ptr, self.domain.asymmetric());
+        std::ptr::NonNull::new(user_t_ptr).map(|nonnull_ptr| {
+            let user_t = unsafe { nonnull_ptr.as_ref() };
+            self.domain.check_domain(user_t.domain(), "HazPtrArray::load");
+            user_t
+        })
+    }
+    // load without the deref, for a ptr that may be retired already, e.g. the next of a node
+    // that was unlinked meanwhile. Deref only once it checked the ptr still is reachable.
+    pub fn protect_raw<T>(&self, i: usize, atomic_ptr: &AtomicPtr<T>) -> *mut T {
+        self.hazptrs[i].get().protect_load(atomic_ptr, self.domain.asymmetric())
+    }
+    // hand-over-hand: after swap(cur, next), slot cur guards what next guarded and the
+    // old cur protection moves to slot next, ready to be re-used for the following node.
+    pub fn swap(&self, i: usize, j: usize) {
+        self.hazptrs[i].swap(&self.hazptrs[j]);
+    }
+    pub fn reset(&self, i: usize) {
+        self.hazptrs[i].get().ptr.store(std::ptr::null_mut(), Ordering::SeqCst);
+    }
+}
+// all N hazptrs go back to the domain together.
+impl<F, const N: usize> Drop for HazPtrArray<'_, F, N> {
+    fn drop(&mut self) {
+        for hazptr in &self.hazptrs {
+            self.domain.release(hazptr.get());
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
 
     struct CountDrops(Arc<AtomicUsize>);
     impl Drop for CountDrops {
@@ -539,8 +1344,33 @@ mod tests {
         }
     }
 
+    // tests asserting on global domain counts or entries run one at a time.
+    static GLOBAL_DOMAIN: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    fn lock_global_domain() -> std::sync::MutexGuard<'static, ()> {
+        GLOBAL_DOMAIN.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    type Answer = HazPtrObjectWrapper<'static, (i32, CountDrops), Global>;
+
+    // Safety of retire's callers:
+    //
+    //  1. The pointer came from Box, so is valid.
+    //  2. The old value is no longer accessible.
+    //  3. The deleter is valid for Box types.
     #[test]
     fn feels_good() {
+        feels_good_with(|old| unsafe { { &mut *old }.retire(deleters::_drop_box) });
+    }
+
+    // same as feels_good, retired through a &'static dyn Deleter.
+    #[test]
+    fn feels_good_dyn_deleter() {
+        feels_good_with(|old| unsafe { { &mut *old }.retire_with(&deleters::drop_box) });
+    }
+
+    // reader and writer walk through protect, retire and reclaim, retire hands old to the domain.
+    fn feels_good_with(retire: impl Fn(*mut Answer)) {
+        let _global = lock_global_domain();
         let drops_42 = Arc::new(AtomicUsize::new(0));
 
         let x = AtomicPtr::new(Box::into_raw(Box::new(
@@ -592,13 +1422,7 @@ mod tests {
         assert_eq!(my_x.0, 42);
         assert_eq!(my_x2.0, 9001);
 
-        // Safety:
-        //
-        //  1. The pointer came from Box, so is valid.
-        //  2. The old value is no longer accessible.
-        //  3. The deleter is valid for Box types.
-        // unsafe { { &mut *old }.retire(&deleters::drop_box) };
-        unsafe { { &mut *old }.retire(deleters::_drop_box) };
+        retire(old);
 
         assert_eq!(drops_42.load(Ordering::SeqCst), 0);
         assert_eq!(my_x.0, 42);
@@ -626,7 +1450,504 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    fn drop_domain_reclaims_retired() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let domain = HazPtrDomain::with_config(&(), DomainConfig::new().reclaim_threshold(1));
+
+        let x = AtomicPtr::new(Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+            &domain,
+            CountDrops(Arc::clone(&drops)),
+        ))));
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let _ = unsafe { h.load(&x) }.expect("not null");
+
+        // retire a few values while the first one is still guarded by h.
+        for _ in 0..4 {
+            let old = x.swap(
+                Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+                    &domain,
+                    CountDrops(Arc::clone(&drops)),
+                ))),
+                Ordering::SeqCst,
+            );
+            unsafe { { &mut *old }.retire(deleters::_drop_box) };
+        }
+        assert_eq!(drops.load(Ordering::SeqCst), 3);
+
+        // the current value is owned by x, retire it too. The first one stays guarded, so only
+        // dropping the domain can reclaim it.
+        let last = x.swap(std::ptr::null_mut(), Ordering::SeqCst);
+        unsafe { { &mut *last }.retire(deleters::_drop_box) };
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+        drop(h);
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+        drop(domain);
+        assert_eq!(drops.load(Ordering::SeqCst), 5);
+    }
+
+    fn count_hazptrs<F>(domain: &HazPtrDomain<F>) -> usize {
+        let mut n = 0;
+        let mut hazptr_ptr = domain.hazptrs.head.load(Ordering::SeqCst);
+        while !hazptr_ptr.is_null() {
+            n += 1;
+            hazptr_ptr = unsafe { &*hazptr_ptr }.next.load(Ordering::SeqCst);
+        }
+        n
+    }
+
+    #[test]
+    fn hazptr_array() {
+        let domain = HazPtrDomain::new(&());
+        let drops_x = Arc::new(AtomicUsize::new(0));
+        let drops_y = Arc::new(AtomicUsize::new(0));
+        let x = AtomicPtr::new(Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+            &domain,
+            (1, CountDrops(Arc::clone(&drops_x))),
+        ))));
+        let y = AtomicPtr::new(Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+            &domain,
+            (2, CountDrops(Arc::clone(&drops_y))),
+        ))));
+
+        let hs = HazPtrArray::<_, 3>::for_domain(&domain);
+        assert_eq!(count_hazptrs(&domain), 3);
+        let my_x = unsafe { hs.load(0, &x) }.expect("not null");
+        let my_y = unsafe { hs.load(1, &y) }.expect("not null");
+        assert_eq!((my_x.0, my_y.0), (1, 2));
+
+        let old_x = x.swap(std::ptr::null_mut(), Ordering::SeqCst);
+        let old_y = y.swap(std::ptr::null_mut(), Ordering::SeqCst);
+        unsafe { { &mut *old_x }.retire(deleters::_drop_box) };
+        unsafe { { &mut *old_y }.retire(deleters::_drop_box) };
+        assert_eq!(domain.eager_reclaim(false), 0);
+
+        // hand over: slot 0 now guards y, the x protection moved to slot 1.
+        hs.swap(0, 1);
+        hs.reset(1);
+        assert_eq!(domain.eager_reclaim(false), 1);
+        assert_eq!(drops_x.load(Ordering::SeqCst), 1);
+        assert_eq!(my_y.0, 2);
+
+        drop(hs);
+        assert_eq!(domain.eager_reclaim(false), 1);
+        assert_eq!(drops_y.load(Ordering::SeqCst), 1);
+
+        // released entries are re-used, nothing new allocated.
+        let _hs = HazPtrArray::<_, 2>::for_domain(&domain);
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let _ = h.get_hazptr_from_domain();
+        assert_eq!(count_hazptrs(&domain), 3);
+    }
+
+    #[test]
+    fn thread_cache_reuses_hazptr() {
+        let _global = lock_global_domain();
+        let hazptr_addr = std::thread::spawn(|| {
+            let mut h = HazPtrHolder::global();
+            let hazptr = h.get_hazptr_from_domain() as *const HazPtr;
+            drop(h);
+            // stashed in this thread's cache, still active, nothing protected.
+            assert!(unsafe { &*hazptr }.active.load(Ordering::SeqCst));
+            assert!(unsafe { &*hazptr }.ptr.load(Ordering::SeqCst).is_null());
+
+            let mut h = HazPtrHolder::global();
+            assert!(std::ptr::eq(h.get_hazptr_from_domain(), hazptr));
+            drop(h);
+            hazptr as usize
+        })
+        .join()
+        .unwrap();
+
+        // thread exit handed the entry back to the global list.
+        let hazptr = unsafe { &*(hazptr_addr as *const HazPtr) };
+        assert!(!hazptr.active.load(Ordering::SeqCst));
+    }
+
+    // cargo test -- --ignored --nocapture bench_acquire_release
+    #[test]
+    #[ignore]
+    fn bench_acquire_release() {
+        const THREADS: usize = 8;
+        const OPS: usize = 200_000;
+        fn bench<F: Sync + 'static>(domain: &HazPtrDomain<F>, label: &str) {
+            let x = AtomicPtr::new(Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+                domain, 42,
+            ))));
+            let start = std::time::Instant::now();
+            std::thread::scope(|s| {
+                for _ in 0..THREADS {
+                    s.spawn(|| {
+                        for _ in 0..OPS {
+                            let mut h = HazPtrHolder::for_domain(domain);
+                            assert_eq!(**unsafe { h.load(&x) }.expect("not null"), 42);
+                        }
+                    });
+                }
+            });
+            let elapsed = start.elapsed();
+            println!(
+                "{}: {} threads, {:?}/op",
+                label,
+                THREADS,
+                elapsed / (THREADS * OPS) as u32
+            );
+            drop(unsafe { Box::from_raw(x.into_inner()) });
+        }
+        let _global = lock_global_domain();
+        bench(HazPtrDomain::global(), "global domain, thread cache");
+        bench(&HazPtrDomain::new(&()), "local domain, list walk");
+    }
+
+    #[test]
+    fn haz_atomic_ptr() {
+        let domain = HazPtrDomain::new(&());
+        let drops_42 = Arc::new(AtomicUsize::new(0));
+        let drops_9001 = Arc::new(AtomicUsize::new(0));
+        let drops_7 = Arc::new(AtomicUsize::new(0));
+        let x = HazAtomicPtr::with_domain(&domain, (42, CountDrops(Arc::clone(&drops_42))));
+
+        // As a reader:
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let my_x = x.load(&mut h);
+        assert_eq!(my_x.0, 42);
+
+        // As a writer, the replaced 42 is retired but still guarded by h.
+        x.store((9001, CountDrops(Arc::clone(&drops_9001))));
+        assert_eq!(drops_42.load(Ordering::SeqCst), 0);
+        assert_eq!(my_x.0, 42);
+
+        // 42 is no longer the current value, cas fails and hands 7 back.
+        let stale = x.compare_exchange(my_x, (7, CountDrops(Arc::clone(&drops_7))));
+        let (seven, count_drops) = stale.expect_err("stale current");
+        assert_eq!(seven, 7);
+        drop(count_drops);
+        assert_eq!(drops_7.load(Ordering::SeqCst), 1);
+
+        drop(h);
+        assert_eq!(domain.eager_reclaim(false), 1);
+        assert_eq!(drops_42.load(Ordering::SeqCst), 1);
+
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let mut h_old = HazPtrHolder::for_domain(&domain);
+        let cur = x.load(&mut h);
+        assert!(x.compare_exchange(cur, (7, CountDrops(Arc::clone(&drops_7)))).is_ok());
+        let old = x.swap((0, CountDrops(Arc::clone(&drops_42))), &mut h_old);
+        assert_eq!((cur.0, old.0), (9001, 7));
+        assert_eq!(domain.eager_reclaim(false), 0);
+
+        drop(h);
+        drop(h_old);
+        // dropping x retires the current value, below the threshold it waits for a reclaim.
+        drop(x);
+        assert_eq!(drops_9001.load(Ordering::SeqCst), 0);
+        assert_eq!(domain.eager_reclaim(false), 3);
+        assert_eq!(drops_9001.load(Ordering::SeqCst), 1);
+        assert_eq!(drops_7.load(Ordering::SeqCst), 2);
+        assert_eq!(drops_42.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "HazAtomicPtr::load: HazPtrDomain")]
+    fn haz_atomic_ptr_other_domain_holder() {
+        let dw = HazPtrDomain::new(&());
+        let dr = HazPtrDomain::new(&());
+        let x = HazAtomicPtr::with_domain(&dw, 42);
+        // the holder's hazptr lives in dr, a retire into dw would not see it.
+        let mut h = HazPtrHolder::for_domain(&dr);
+        let _ = x.load(&mut h);
+    }
+
+    // writers keep replacing the value while readers check they never see a reclaimed one.
+    fn protect_vs_reclaim(domain: &HazPtrDomain<()>) {
+        const MAGIC: usize = 0x5eed;
+        struct Checked(usize, Arc<AtomicUsize>);
+        impl Drop for Checked {
+            fn drop(&mut self) {
+                assert_eq!(self.0, MAGIC);
+                self.0 = 0;
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let drops = Arc::new(AtomicUsize::new(0));
+        let x = HazAtomicPtr::with_domain(domain, Checked(MAGIC, Arc::clone(&drops)));
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    let mut h = HazPtrHolder::for_domain(domain);
+                    for _ in 0..2000 {
+                        assert_eq!(x.load(&mut h).0, MAGIC);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    x.store(Checked(MAGIC, Arc::clone(&drops)));
+                }
+            });
+        });
+        drop(x);
+        domain.eager_reclaim(false);
+        assert_eq!(drops.load(Ordering::SeqCst), 1001);
+    }
+
+    // a low threshold, so the writer reclaims while the readers protect.
+    #[test]
+    fn seqcst_fence_domain() {
+        let config = DomainConfig::new().fence(FenceMode::SeqCst).reclaim_threshold(8);
+        protect_vs_reclaim(&HazPtrDomain::with_config(&(), config));
+    }
+
+    #[test]
+    fn asymmetric_fence_domain() {
+        let config = DomainConfig::new().fence(FenceMode::Asymmetric).reclaim_threshold(8);
+        protect_vs_reclaim(&HazPtrDomain::with_config(&(), config));
+    }
+
+    #[test]
+    fn retire_below_threshold_defers_reclaim() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let domain = HazPtrDomain::with_config(&(), DomainConfig::new().reclaim_threshold(4));
+        let x = HazAtomicPtr::with_domain(&domain, CountDrops(Arc::clone(&drops)));
+        for _ in 0..3 {
+            x.store(CountDrops(Arc::clone(&drops)));
+        }
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+        // the 4th retired node reaches the threshold and reclaims the whole shard.
+        x.store(CountDrops(Arc::clone(&drops)));
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+    }
+
+    // cargo test -- --ignored --nocapture bench_reader_fence
+    #[test]
+    #[ignore]
+    fn bench_reader_fence() {
+        const READERS: usize = 8;
+        const LOADS: usize = 1_000_000;
+        fn bench(fence: FenceMode) {
+            let domain = HazPtrDomain::with_config(&(), DomainConfig::new().fence(fence));
+            let x = HazAtomicPtr::with_domain(&domain, 42);
+            let done = AtomicBool::new(false);
+            let start = std::time::Instant::now();
+            std::thread::scope(|s| {
+                let readers: Vec<_> = (0..READERS)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let mut h = HazPtrHolder::for_domain(&domain);
+                            for _ in 0..LOADS {
+                                assert_eq!(**x.load(&mut h), 42);
+                            }
+                        })
+                    })
+                    .collect();
+                // a slow writer, so bulk_reclaim and its heavy fence run during the reads.
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        x.store(42);
+                        std::thread::sleep(std::time::Duration::from_micros(100));
+                    }
+                });
+                for reader in readers {
+                    reader.join().unwrap();
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+            let elapsed = start.elapsed();
+            println!(
+                "{:?} (membarrier {}): {} readers, {:.1} Mloads/s",
+                fence,
+                membarrier::is_supported(),
+                READERS,
+                (READERS * LOADS) as f64 / elapsed.as_secs_f64() / 1e6
+            );
+        }
+        bench(FenceMode::SeqCst);
+        bench(FenceMode::Asymmetric);
+    }
+
+    // every writer thread retires into its own shard while readers pin some of the values.
+    fn retire_from_threads(domain: &HazPtrDomain<()>, threads: usize, retires: usize) {
+        std::thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    let x = HazAtomicPtr::with_domain(domain, 0);
+                    let mut h = HazPtrHolder::for_domain(domain);
+                    for i in 0..retires {
+                        if i % 64 == 0 {
+                            let _ = x.load(&mut h);
+                        }
+                        x.store(i);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn sharded_retired_lists() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let domain = HazPtrDomain::with_config(&(), DomainConfig::new().retired_shards(4));
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut h = HazPtrHolder::for_domain(&domain);
+                    let x = HazAtomicPtr::with_domain(&domain, CountDrops(Arc::clone(&drops)));
+                    for _ in 0..100 {
+                        let _ = x.load(&mut h);
+                        x.store(CountDrops(Arc::clone(&drops)));
+                    }
+                });
+            }
+        });
+        retire_from_threads(&domain, 4, 100);
+        drop(domain);
+        assert_eq!(drops.load(Ordering::SeqCst), 4 * 101);
+    }
+
+    // cargo test -- --ignored --nocapture bench_retired_shards
+    #[test]
+    #[ignore]
+    fn bench_retired_shards() {
+        const RETIRES: usize = 100_000;
+        for threads in [1, 4, 8, 16] {
+            for shards in [1, 4, MAX_RETIRED_SHARDS] {
+                let domain =
+                    HazPtrDomain::with_config(&(), DomainConfig::new().retired_shards(shards));
+                let start = std::time::Instant::now();
+                retire_from_threads(&domain, threads, RETIRES);
+                let elapsed = start.elapsed();
+                println!(
+                    "{:>2} writer threads, {:>2} shards: {:?}/retire",
+                    threads,
+                    shards,
+                    elapsed / (threads * RETIRES) as u32
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hazard_set_lookup() {
+        let protected: Vec<*mut u8> = (1..=100).rev().map(|i| (i * 8) as *mut u8).collect();
+        for linear_scan_max in [0, LINEAR_SCAN_MAX, usize::MAX] {
+            let set = HazardSet::new(protected.clone(), 100, linear_scan_max);
+            assert!(set.contains(8 as *mut u8));
+            assert!(set.contains(800 as *mut u8));
+            assert!(!set.contains(4 as *mut u8));
+            assert!(!set.contains(808 as *mut u8));
+        }
+    }
+
+    // cargo test -- --ignored --nocapture bench_hazard_set
+    #[test]
+    #[ignore]
+    fn bench_hazard_set() {
+        const ROUNDS: usize = 200;
+        // spread like heap addresses, every other retired one is protected.
+        let addr = |i: usize| (i.wrapping_mul(0x9e37_79b9) % (1 << 30) * 16) as *mut u8;
+        for (hazptrs, retired) in [4, 8, 16, 64, 512].iter().flat_map(|&h| [(h, 4), (h, 1024)]) {
+            let retired: Vec<*mut u8> = (0..retired).map(addr).collect();
+            let protected: Vec<*mut u8> = (0..hazptrs).map(|i| addr(i * 2)).collect();
+            for (label, linear_scan_max) in [("linear", usize::MAX), ("sorted", 0)] {
+                let mut buf = Vec::with_capacity(hazptrs);
+                let start = std::time::Instant::now();
+                let mut hits = 0;
+                for _ in 0..ROUNDS {
+                    buf.clear();
+                    buf.extend_from_slice(&protected);
+                    let set = HazardSet::new(buf, usize::MAX, linear_scan_max);
+                    hits += retired.iter().filter(|&&ptr| set.contains(ptr)).count();
+                    buf = set.ptrs;
+                }
+                println!(
+                    "{:>3} hazptrs, {:>4} retired, {}: {:?}/scan ({} hits)",
+                    hazptrs,
+                    retired.len(),
+                    label,
+                    start.elapsed() / ROUNDS as u32,
+                    hits / ROUNDS
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cohort_reclaims_members() {
+        let domain = HazPtrDomain::new(&());
+        let drops = Arc::new(AtomicUsize::new(0));
+        let new_node = || {
+            Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+                &domain,
+                CountDrops(Arc::clone(&drops)),
+            )))
+        };
+        let x = AtomicPtr::new(new_node());
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let _ = unsafe { h.load(&x) }.expect("not null");
+
+        let cohort = HazPtrCohort::for_domain(&domain);
+        // a whole unlinked sublist, the first node is still protected by h.
+        unsafe { cohort.retire(x.swap(std::ptr::null_mut(), Ordering::SeqCst), deleters::_drop_box) };
+        for _ in 0..2 {
+            unsafe { cohort.retire(new_node(), deleters::_drop_box) };
+        }
+        unsafe { cohort.retire_with(new_node(), &deleters::drop_box) };
+        // cohort members never go through the domain's retired list.
+        assert_eq!(domain.eager_reclaim(false), 0);
+        // one protected member holds back the whole cohort.
+        assert_eq!(cohort.reclaim(), 0);
+        assert_eq!(cohort.len(), 4);
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+        drop(h);
+        assert_eq!(cohort.reclaim(), 4);
+        assert!(cohort.is_empty());
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn drop_cohort_waits_for_readers() {
+        let domain = HazPtrDomain::new(&());
+        let drops = Arc::new(AtomicUsize::new(0));
+        let new_node = || {
+            Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+                &domain,
+                CountDrops(Arc::clone(&drops)),
+            )))
+        };
+        let x = AtomicPtr::new(new_node());
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let _ = unsafe { h.load(&x) }.expect("not null");
+
+        let cohort = HazPtrCohort::for_domain(&domain);
+        unsafe { cohort.retire(x.swap(std::ptr::null_mut(), Ordering::SeqCst), deleters::_drop_box) };
+        unsafe { cohort.retire(new_node(), deleters::_drop_box) };
+        thread::scope(|s| {
+            let dropper = s.spawn(move || drop(cohort));
+            thread::sleep(Duration::from_millis(50));
+            // drop is still waiting for h, nothing freed yet.
+            assert!(!dropper.is_finished());
+            assert_eq!(drops.load(Ordering::SeqCst), 0);
+            drop(h);
+            dropper.join().unwrap();
+        });
+        // every member is freed by the time drop returns, none left for the domain.
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+        assert_eq!(domain.eager_reclaim(false), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "still active")]
+    fn drop_domain_with_leaked_holder() {
+        let domain = HazPtrDomain::new(&());
+        let mut h = HazPtrHolder::for_domain(&domain);
+        let _ = h.get_hazptr_from_domain();
+        std::mem::forget(h);
+        drop(domain);
+    }
+
+    #[test]
+    #[cfg_attr(not(any(debug_assertions, hazptr_check_domain)), ignore)]
+    #[should_panic(expected = "HazPtrHolder::load: object belongs to HazPtrDomain")]
     fn feels_bad() {
         let dw = HazPtrDomain::new(&());
         let dr = HazPtrDomain::new(&());
@@ -641,7 +1962,98 @@ mod tests {
         // Reader uses a different domain thant the writer!
         let mut h = HazPtrHolder::for_domain(&dr);
 
-        // Let's hope this catches the error (at least in debug mode).
+        // Caught by the domain check (always in debug mode).
         let _ = unsafe { h.load(&x) }.expect("not null");
     }
+
+    #[test]
+    #[cfg_attr(not(any(debug_assertions, hazptr_check_domain)), ignore)]
+    #[should_panic(expected = "retire: object belongs to HazPtrDomain")]
+    fn retire_into_other_domain() {
+        let dw = HazPtrDomain::new(&());
+        let dr = HazPtrDomain::new(&());
+
+        let drops_42 = Arc::new(AtomicUsize::new(0));
+        let old = Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+            &dw,
+            (42, CountDrops(Arc::clone(&drops_42))),
+        )));
+
+        // Writer retires into a domain the object does not belong to.
+        unsafe { dr.retire(old, deleters::_drop_box) };
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use sync::{thread, Arc};
+
+    const MAGIC: usize = 0x5eed;
+    struct Checked(usize, Arc<AtomicUsize>);
+    impl Drop for Checked {
+        fn drop(&mut self) {
+            assert_eq!(self.0, MAGIC);
+            self.0 = 0;
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    fn checked(drops: &Arc<AtomicUsize>) -> *mut HazPtrObjectWrapper<'static, Checked, Global> {
+        Box::into_raw(Box::new(HazPtrObjectWrapper::with_global_domain(Checked(MAGIC, Arc::clone(drops)))))
+    }
+
+    // a reader protects the value while a writer swaps it out, retires and reclaims it.
+    // whatever the interleaving, the reader sees a live value and nothing is freed twice.
+    #[test]
+    fn protect_vs_retire() {
+        sync::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let x = Arc::new(AtomicPtr::new(checked(&drops)));
+            let reader = thread::spawn({
+                let x = Arc::clone(&x);
+                move || {
+                    let mut h = HazPtrHolder::global();
+                    let value = unsafe { h.load(&x) }.expect("not null");
+                    assert_eq!(value.0, MAGIC);
+                }
+            });
+            let old = x.swap(checked(&drops), Ordering::SeqCst);
+            unsafe { { &mut *old }.retire(deleters::_drop_box) };
+            HazPtrDomain::global().eager_reclaim(false);
+            reader.join().unwrap();
+
+            let last = x.swap(std::ptr::null_mut(), Ordering::SeqCst);
+            unsafe { { &mut *last }.retire(deleters::_drop_box) };
+            HazPtrDomain::global().eager_reclaim(false);
+            assert_eq!(drops.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    // two readers race on acquiring hazptrs from the list while the writer reclaims.
+    #[test]
+    fn acquire_vs_reclaim() {
+        sync::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let x = Arc::new(AtomicPtr::new(checked(&drops)));
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let x = Arc::clone(&x);
+                    thread::spawn(move || {
+                        let mut h = HazPtrHolder::global();
+                        assert_eq!(unsafe { h.load(&x) }.expect("not null").0, MAGIC);
+                    })
+                })
+                .collect();
+            let old = x.swap(checked(&drops), Ordering::SeqCst);
+            unsafe { { &mut *old }.retire(deleters::_drop_box) };
+            HazPtrDomain::global().eager_reclaim(false);
+            for reader in readers {
+                reader.join().unwrap();
+            }
+            let last = x.swap(std::ptr::null_mut(), Ordering::SeqCst);
+            unsafe { { &mut *last }.retire(deleters::_drop_box) };
+            HazPtrDomain::global().eager_reclaim(false);
+            assert_eq!(drops.load(Ordering::SeqCst), 2);
+        });
+    }
 }
@@ -0,0 +1,778 @@
+#![allow(dead_code)]
+
+use crate::sync;
+use sync::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::haphazard_domain::{
+    deleters, Global, HazPtrDomain, HazPtrHolder, HazPtrObject, HazPtrObjectWrapper,
+};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+
+// Lock-free hash map, a split-ordered list (Shalev, Shavit) of nodes whose removal is
+// Michael's list, a consumer of the hazard pointer domain.
+//
+// One sorted list holds every node, ordered by the bit-reversed hash. A bucket is a dummy node
+// in that list, its keys follow it up to the next bucket's dummy. Doubling the buckets moves
+// nothing: bucket b splits into b and b + old size, whose dummy is linked in on first use
+// right where b's keys divide. So writers only CAS links, and growth is one CAS of size.
+//
+// Remove marks the next of a node before the node is unlinked, a marked link is never
+// followed or changed again. Whoever CAS the link around the node retires it into the domain.
+// Walkers hold the node before and the one they stand on, each through a HazPtrHolder. A load
+// from the link of a node with an unmarked next proves the loaded node was still linked.
+//
+// Values live in a box of their own, retired when replaced or removed. A node's value word
+// gets DEAD when the key is removed, the insert that finds a dead node helps unlink it and
+// links a new one. Readers get &V through the caller's HazPtrHolder, valid while it holds it,
+// so neither K nor V has to be Clone.
+//
+// list: dummy 0 -> key -> .. -> dummy 2 -> .. -> dummy 1 -> .. -> dummy 3 -> .. -> null
+
+pub const DEFAULT_BUCKETS: usize = 64;
+// average nodes per bucket before the buckets double.
+pub const MAX_LOAD: usize = 2;
+// a dummy's key is its reversed bucket with the low bit clear, so the top bit stays unused.
+const MAX_BUCKETS: usize = 1 << (usize::BITS - 1);
+
+// in a node's next: the node is removed, its link must not be followed or changed.
+const MARKED: usize = 1;
+// in a node's value: the key is removed, the value is retired by its remover.
+const DEAD: usize = 1;
+
+type NodePtr<'domain, K, F> = *mut HazPtrObjectWrapper<'domain, Node<K>, F>;
+type ValuePtr<'domain, V, F> = *mut HazPtrObjectWrapper<'domain, V, F>;
+// a bucket's dummy, null until first used.
+type Slot<'domain, K, F> = AtomicPtr<HazPtrObjectWrapper<'domain, Node<K>, F>>;
+
+struct Node<K> {
+    // reversed hash, set low bit for keys, clear for dummies, the list's sort key.
+    order: usize,
+    key: Option<K>, // None in dummies
+    value: AtomicUsize,
+    next: AtomicUsize,
+}
+
+fn key_order(hash: usize) -> usize {
+    (hash | MAX_BUCKETS).reverse_bits()
+}
+fn dummy_order(bucket: usize) -> usize {
+    bucket.reverse_bits()
+}
+// a marked word's node may be reclaimed already, it never gets a ptr.
+fn node_of<'domain, K, F>(w: usize) -> NodePtr<'domain, K, F> {
+    if w & MARKED != 0 {
+        ptr::null_mut()
+    } else {
+        w as NodePtr<'domain, K, F>
+    }
+}
+fn value_of<'domain, V, F>(w: usize) -> ValuePtr<'domain, V, F> {
+    if w & DEAD != 0 {
+        ptr::null_mut()
+    } else {
+        w as ValuePtr<'domain, V, F>
+    }
+}
+// bucket 0 is segment 0, the others are in the segment of their bit length, which holds
+// 2^(length-1) buckets. Segments are allocated on first use and never move.
+fn segment_of(bucket: usize) -> (usize, usize) {
+    match bucket {
+        0 => (0, 0),
+        b => {
+            let seg = (usize::BITS - b.leading_zeros()) as usize;
+            (seg, b - (1 << (seg - 1)))
+        }
+    }
+}
+fn segment_len(seg: usize) -> usize {
+    if seg == 0 {
+        1
+    } else {
+        1 << (seg - 1)
+    }
+}
+// the bucket b splits off from when the size doubled past it.
+fn parent(bucket: usize) -> usize {
+    bucket & !(1 << (usize::BITS - 1 - bucket.leading_zeros()))
+}
+
+// the node before the one we stand on and that one, both kept from reclaim.
+struct Cursor<'domain, F> {
+    prev: HazPtrHolder<'domain, F>,
+    cur: HazPtrHolder<'domain, F>,
+}
+impl<'domain, F> Cursor<'domain, F> {
+    fn advance(&mut self) {
+        mem::swap(&mut self.prev, &mut self.cur);
+    }
+}
+
+// where a key is, or would be linked: link is the next of the node before, or a dummy's,
+// cur the first node not ordered before the key. Both stay valid while the cursor holds them.
+struct Position<'domain, K, F> {
+    link: *const AtomicUsize,
+    cur: NodePtr<'domain, K, F>,
+    found: bool,
+}
+
+pub struct HazHashMap<'domain, K: 'domain, V: 'domain, F: 'static, S = RandomState> {
+    // dummies by bucket, segment i holds segment_len(i) of them.
+    segments: Box<[AtomicPtr<Slot<'domain, K, F>>]>,
+    size: AtomicUsize, // buckets in use, a power of two
+    len: AtomicUsize,
+    hasher: S,
+    domain: &'domain HazPtrDomain<F>,
+    // owns K and V behind the raw ptrs, and shares them between threads like Arc<(K, V)>.
+    entries: PhantomData<Arc<(K, V)>>,
+}
+
+impl<K, V> HazHashMap<'static, K, V, Global> {
+    pub fn new() -> Self {
+        HazHashMap::with_domain(HazPtrDomain::global(), DEFAULT_BUCKETS)
+    }
+}
+impl<K, V> Default for HazHashMap<'static, K, V, Global> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<'domain, K: 'domain, V: 'domain, F: 'static> HazHashMap<'domain, K, V, F> {
+    pub fn with_domain(domain: &'domain HazPtrDomain<F>, buckets: usize) -> Self {
+        HazHashMap::with_hasher(domain, buckets, RandomState::new())
+    }
+}
+impl<'domain, K, V, F, S> HazHashMap<'domain, K, V, F, S>
+where
+    K: 'domain,
+    V: 'domain,
+    F: 'static,
+{
+    // buckets is rounded up to a power of two, the map grows from there.
+    pub fn with_hasher(domain: &'domain HazPtrDomain<F>, buckets: usize, hasher: S) -> Self {
+        assert!(buckets > 0 && buckets <= MAX_BUCKETS);
+        let map = Self {
+            segments: (0..usize::BITS).map(|_| AtomicPtr::new(ptr::null_mut())).collect(),
+            size: AtomicUsize::new(buckets.next_power_of_two()),
+            len: AtomicUsize::new(0),
+            hasher,
+            domain,
+            entries: PhantomData,
+        };
+        // the list starts at bucket 0's dummy, every other dummy is linked behind it.
+        map.slot(0).store(map.boxed_node(dummy_order(0), None, 0), Ordering::SeqCst);
+        map
+    }
+    pub fn holder(&self) -> HazPtrHolder<'domain, F> {
+        HazPtrHolder::for_domain(self.domain)
+    }
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // a holder of another domain does not keep our values from reclaim.
+    fn assert_holder(&self, holder: &HazPtrHolder<'domain, F>, op: &str) {
+        assert!(
+            std::ptr::eq(holder.domain(), self.domain),
+            "{}: holder belongs to HazPtrDomain {:p}, not to the map's HazPtrDomain {:p}",
+            op,
+            holder.domain(),
+            self.domain
+        );
+    }
+    fn cursor(&self) -> Cursor<'domain, F> {
+        Cursor {
+            prev: self.holder(),
+            cur: self.holder(),
+        }
+    }
+    fn boxed_node(&self, order: usize, key: Option<K>, value: usize) -> NodePtr<'domain, K, F> {
+        Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+            self.domain,
+            Node {
+                order,
+                key,
+                value: AtomicUsize::new(value),
+                next: AtomicUsize::new(0),
+            },
+        )))
+    }
+    fn boxed_value(&self, value: V) -> ValuePtr<'domain, V, F> {
+        Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(self.domain, value)))
+    }
+    // the dummy slot of bucket, its segment allocated if need be.
+    fn slot(&self, bucket: usize) -> &Slot<'domain, K, F> {
+        let (seg, i) = segment_of(bucket);
+        let len = segment_len(seg);
+        let mut segment = self.segments[seg].load(Ordering::SeqCst);
+        if segment.is_null() {
+            let new: Box<[Slot<'domain, K, F>]> =
+                (0..len).map(|_| AtomicPtr::new(ptr::null_mut())).collect();
+            let new = Box::into_raw(new) as *mut Slot<'domain, K, F>;
+            segment = match self.segments[seg].compare_exchange(
+                ptr::null_mut(),
+                new,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => new,
+                Err(theirs) => {
+                    // never published, ours to free.
+                    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(new, len)) });
+                    theirs
+                }
+            };
+        }
+        // Safety: segments are freed only by Drop.
+        unsafe { &*segment.add(i) }
+    }
+}
+
+impl<'domain, K, V, F, S> HazHashMap<'domain, K, V, F, S>
+where
+    K: Hash + Eq + 'domain,
+    V: 'domain,
+    F: 'static,
+    S: BuildHasher,
+{
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize
+    }
+    // the dummy of bucket, linked in behind its parent's on first use.
+    fn dummy(&self, bucket: usize) -> NodePtr<'domain, K, F> {
+        let slot = self.slot(bucket);
+        let dummy = slot.load(Ordering::SeqCst);
+        if !dummy.is_null() {
+            return dummy;
+        }
+        let parent = self.dummy(parent(bucket));
+        let new = self.boxed_node(dummy_order(bucket), None, 0);
+        let dummy = match self.link(parent, new, None::<&K>, &mut self.cursor()) {
+            Ok(new) => new,
+            Err(theirs) => {
+                // Safety: never published.
+                drop(unsafe { Box::from_raw(new) });
+                theirs
+            }
+        };
+        // dummies are never unlinked, every thread that links or finds it stores the same.
+        slot.store(dummy, Ordering::SeqCst);
+        dummy
+    }
+    fn head(&self, hash: usize) -> NodePtr<'domain, K, F> {
+        self.dummy(hash & (self.size.load(Ordering::SeqCst) - 1))
+    }
+    // Michael's search from head: unlinks the marked nodes on the way and retires them.
+    // Restarts when the node it stands on got removed, its next may be reclaimed already.
+    fn find<Q>(
+        &self,
+        head: NodePtr<'domain, K, F>,
+        order: usize,
+        key: Option<&Q>,
+        cursor: &mut Cursor<'domain, F>,
+    ) -> Position<'domain, K, F>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        'restart: loop {
+            // Safety: dummies are freed only by Drop.
+            let mut link: *const AtomicUsize = &unsafe { &*head }.next;
+            // Safety (every load_word below): links hold 0 or nodes of this map, a node is
+            // retired only after it is unlinked, and node_of never maps a marked word.
+            let (mut w, _) = unsafe { cursor.cur.load_word(&*link, node_of::<K, F>) };
+            loop {
+                if w & MARKED != 0 {
+                    continue 'restart;
+                }
+                let cur = node_of::<K, F>(w);
+                if cur.is_null() {
+                    return Position { link, cur, found: false };
+                }
+                // Safety: cursor.cur protected it, then saw it still behind an unmarked link.
+                let node = unsafe { &*cur };
+                let next = node.next.load(Ordering::SeqCst);
+                if next & MARKED != 0 {
+                    // Safety: link is in the node cursor.prev holds, or in a dummy.
+                    let link_ref = unsafe { &*link };
+                    if link_ref
+                        .compare_exchange(w, next & !MARKED, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_err()
+                    {
+                        continue 'restart;
+                    }
+                    // Safety: we unlinked it, only once, and cursor.cur holds it meanwhile.
+                    unsafe { { &mut *cur }.retire(deleters::_drop_box) };
+                    w = unsafe { cursor.cur.load_word(link_ref, node_of::<K, F>) }.0;
+                    continue;
+                }
+                let same_key = match (&node.key, key) {
+                    (Some(k), Some(key)) => k.borrow() == key,
+                    _ => true, // dummies: equal orders mean the same bucket
+                };
+                if node.order > order || (node.order == order && same_key) {
+                    let found = node.order == order;
+                    return Position { link, cur, found };
+                }
+                link = &node.next;
+                cursor.advance();
+                w = unsafe { cursor.cur.load_word(&*link, node_of::<K, F>) }.0;
+            }
+        }
+    }
+    // link new into the list from head, unless a node with its key is there. Err has that
+    // node, which the cursor holds.
+    fn link<Q>(
+        &self,
+        head: NodePtr<'domain, K, F>,
+        new: NodePtr<'domain, K, F>,
+        key: Option<&Q>,
+        cursor: &mut Cursor<'domain, F>,
+    ) -> Result<NodePtr<'domain, K, F>, NodePtr<'domain, K, F>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        // Safety: not published yet, ours.
+        let node = unsafe { &*new };
+        loop {
+            let pos = self.find(head, node.order, key, cursor);
+            if pos.found {
+                return Err(pos.cur);
+            }
+            node.next.store(pos.cur as usize, Ordering::SeqCst);
+            // Safety: the cursor holds the node of link.
+            if unsafe { &*pos.link }
+                .compare_exchange(pos.cur as usize, new as usize, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Ok(new);
+            }
+        }
+    }
+    // a new key was linked, double the buckets once they average MAX_LOAD nodes.
+    fn linked_one(&self) {
+        let len = self.len.fetch_add(1, Ordering::SeqCst) + 1;
+        let size = self.size.load(Ordering::SeqCst);
+        if len > size * MAX_LOAD && size < MAX_BUCKETS {
+            // a racing writer doubled it already if this fails.
+            let _ = self.size.compare_exchange(size, size * 2, Ordering::SeqCst, Ordering::SeqCst);
+        }
+    }
+    // mark the next of node, a removed key's, and unlink it. The remover and the inserts that
+    // find its value dead all do this, the node must not stay in the way of new inserts.
+    fn unlink<Q>(
+        &self,
+        head: NodePtr<'domain, K, F>,
+        node: &Node<K>,
+        key: &Q,
+        cursor: &mut Cursor<'domain, F>,
+    ) where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        node.next.fetch_or(MARKED, Ordering::SeqCst);
+        // find unlinks every marked node it passes.
+        self.find(head, node.order, Some(key), cursor);
+    }
+    // the live value of node, protected by holder, or None when the key was removed.
+    fn load_value<'l>(
+        node: &Node<K>,
+        holder: &'l mut HazPtrHolder<'domain, F>,
+    ) -> (usize, Option<&'l HazPtrObjectWrapper<'domain, V, F>>) {
+        // Safety: value words hold a value of this map or DEAD, which value_of never maps. A
+        // value is retired only after its word was changed.
+        unsafe { holder.load_word(&node.value, value_of) }
+    }
+
+    // the returned value is valid until holder is reset, re-used or dropped.
+    pub fn get<'l, Q>(&self, key: &Q, holder: &'l mut HazPtrHolder<'domain, F>) -> Option<&'l V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.assert_holder(holder, "HazHashMap::get");
+        let hash = self.hash(key);
+        let mut cursor = self.cursor();
+        let pos = self.find(self.head(hash), key_order(hash), Some(key), &mut cursor);
+        if !pos.found {
+            return None;
+        }
+        // Safety: the cursor holds it.
+        Self::load_value(unsafe { &*pos.cur }, holder).1.map(|v| &**v)
+    }
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key, &mut self.holder()).is_some()
+    }
+    // returns the previous value of key, if any, protected by holder.
+    pub fn insert<'l>(
+        &self,
+        key: K,
+        value: V,
+        holder: &'l mut HazPtrHolder<'domain, F>,
+    ) -> Option<&'l V> {
+        self.assert_holder(holder, "HazHashMap::insert");
+        let hash = self.hash(&key);
+        let head = self.head(hash);
+        let value = self.boxed_value(value);
+        let new = self.boxed_node(key_order(hash), Some(key), value as usize);
+        // Safety: ours until linked.
+        let key = unsafe { &*new }.key.as_ref().expect("not a dummy");
+        let mut cursor = self.cursor();
+        loop {
+            let cur = match self.link(head, new, Some(key), &mut cursor) {
+                Ok(_) => {
+                    self.linked_one();
+                    return None;
+                }
+                // Safety: the cursor holds it.
+                Err(cur) => unsafe { &*cur },
+            };
+            // old as a raw ptr again, a conditionally returned borrow of holder is rejected by
+            // the borrow checker.
+            let (w, old) = Self::load_value(cur, holder);
+            if old.is_none() {
+                self.unlink(head, cur, key, &mut cursor);
+                continue;
+            }
+            let old = value_of::<V, F>(w);
+            if cur
+                .value
+                .compare_exchange(w, value as usize, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                // Safety: new never got published, and value now is cur's. old was swapped
+                // out by us, holder protects it.
+                drop(unsafe { Box::from_raw(new) });
+                unsafe { { &mut *old }.retire(deleters::_drop_box) };
+                return Some(unsafe { &**old });
+            }
+            // another insert replaced it, or a remove killed it, look again.
+        }
+    }
+    // returns the removed value of key, if any, protected by holder.
+    pub fn remove<'l, Q>(&self, key: &Q, holder: &'l mut HazPtrHolder<'domain, F>) -> Option<&'l V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.assert_holder(holder, "HazHashMap::remove");
+        let hash = self.hash(key);
+        let head = self.head(hash);
+        let mut cursor = self.cursor();
+        loop {
+            let pos = self.find(head, key_order(hash), Some(key), &mut cursor);
+            if !pos.found {
+                return None;
+            }
+            // Safety: the cursor holds it.
+            let cur = unsafe { &*pos.cur };
+            let w = Self::load_value(cur, holder).0;
+            let old = value_of::<V, F>(w);
+            if old.is_null() {
+                // dead: removed by another remove, whose unlink is on its way.
+                return None;
+            }
+            if cur
+                .value
+                .compare_exchange(w, w | DEAD, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                self.len.fetch_sub(1, Ordering::SeqCst);
+                self.unlink(head, cur, key, &mut cursor);
+                // Safety: killed by us, only once, holder protects it.
+                unsafe { { &mut *old }.retire(deleters::_drop_box) };
+                return Some(unsafe { &**old });
+            }
+        }
+    }
+    // f runs at most once, and only when key was absent. If a racing insert links key first,
+    // its value is returned and the one f made is dropped.
+    pub fn compute_if_absent<'l>(
+        &self,
+        key: K,
+        f: impl FnOnce(&K) -> V,
+        holder: &'l mut HazPtrHolder<'domain, F>,
+    ) -> &'l V {
+        self.assert_holder(holder, "HazHashMap::compute_if_absent");
+        if let Some(found) = self.get(&key, holder).map(|v| v as *const V) {
+            return unsafe { &*found };
+        }
+        let hash = self.hash(&key);
+        let head = self.head(hash);
+        let value = self.boxed_value(f(&key));
+        // protected before it is published, a remove may retire it right after.
+        // Safety: ours, not retired.
+        let ours = unsafe { holder.protect_ptr(value) } as *const HazPtrObjectWrapper<'domain, V, F>;
+        let new = self.boxed_node(key_order(hash), Some(key), value as usize);
+        // Safety: ours until linked.
+        let key = unsafe { &*new }.key.as_ref().expect("not a dummy");
+        let mut cursor = self.cursor();
+        loop {
+            let cur = match self.link(head, new, Some(key), &mut cursor) {
+                Ok(_) => {
+                    self.linked_one();
+                    return unsafe { &*ours };
+                }
+                // Safety: the cursor holds it.
+                Err(cur) => unsafe { &*cur },
+            };
+            let (_, theirs) = Self::load_value(cur, holder);
+            if let Some(theirs) = theirs.map(|v| v as *const HazPtrObjectWrapper<'domain, V, F>) {
+                // Safety: neither got published.
+                drop(unsafe { Box::from_raw(new) });
+                drop(unsafe { Box::from_raw(value) });
+                return unsafe { &*theirs };
+            }
+            // dead, holder no longer protects ours, protect it again before linking.
+            unsafe { holder.protect_ptr(value) };
+            self.unlink(head, cur, key, &mut cursor);
+        }
+    }
+}
+
+// no reader walks the list any more, but holders may still protect values handed out.
+impl<'domain, K: 'domain, V: 'domain, F: 'static, S> Drop for HazHashMap<'domain, K, V, F, S> {
+    fn drop(&mut self) {
+        // every node is on the list from bucket 0's dummy, removed ones not unlinked yet too.
+        let mut cur = self.slot(0).load(Ordering::SeqCst);
+        while !cur.is_null() {
+            // Safety: linked, so not retired, and nobody else walks the list.
+            let node = unsafe { Box::from_raw(cur) };
+            let value = value_of::<V, F>(node.value.load(Ordering::SeqCst));
+            if node.key.is_some() && !value.is_null() {
+                // Safety: the value of a live key, retired only here.
+                unsafe { { &mut *value }.retire(deleters::_drop_box) };
+            }
+            cur = node_of(node.next.load(Ordering::SeqCst) & !MARKED);
+        }
+        for (seg, segment) in self.segments.iter().enumerate() {
+            let segment = segment.load(Ordering::SeqCst);
+            if !segment.is_null() {
+                let len = segment_len(seg);
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(segment, len)) });
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // not Clone: every value is created once and must be dropped exactly once.
+    // the magic is checked on read and on drop to catch a reclaimed node still in use.
+    const MAGIC: usize = 0x5eed;
+    struct CountDrops {
+        magic: usize,
+        val: usize,
+        dropped: Arc<AtomicUsize>,
+    }
+    impl CountDrops {
+        fn new(val: usize, created: &Arc<AtomicUsize>, dropped: &Arc<AtomicUsize>) -> Self {
+            created.fetch_add(1, Ordering::SeqCst);
+            CountDrops {
+                magic: MAGIC,
+                val,
+                dropped: Arc::clone(dropped),
+            }
+        }
+        fn val(&self) -> usize {
+            assert_eq!(self.magic, MAGIC);
+            self.val
+        }
+    }
+    impl Drop for CountDrops {
+        fn drop(&mut self) {
+            assert_eq!(self.magic, MAGIC);
+            self.magic = 0;
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn buckets<K, V, F, S>(map: &HazHashMap<'_, K, V, F, S>) -> usize {
+        map.size.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let domain = HazPtrDomain::new(&());
+        let map = HazHashMap::with_domain(&domain, 4);
+        let mut h = map.holder();
+
+        assert_eq!(map.insert("a", 1, &mut h), None);
+        assert_eq!(map.insert("b", 2, &mut h), None);
+        assert_eq!(map.insert("a", 3, &mut h), Some(&1));
+        assert_eq!(map.get("a", &mut h), Some(&3));
+        assert_eq!(map.get("c", &mut h), None);
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.remove("a", &mut h), Some(&3));
+        assert_eq!(map.remove("a", &mut h), None);
+        assert!(!map.contains_key("a"));
+        assert!(map.contains_key("b"));
+        assert_eq!(map.len(), 1);
+
+        let mut calls = 0;
+        assert_eq!(
+            *map.compute_if_absent(
+                "c",
+                |_| {
+                    calls += 1;
+                    4
+                },
+                &mut h
+            ),
+            4
+        );
+        assert_eq!(
+            *map.compute_if_absent(
+                "c",
+                |_| {
+                    calls += 1;
+                    5
+                },
+                &mut h
+            ),
+            4
+        );
+        assert_eq!(calls, 1);
+
+        // a removed key's node is unlinked, a new one takes its place.
+        assert_eq!(map.insert("a", 6, &mut h), None);
+        assert_eq!(map.get("a", &mut h), Some(&6));
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn reader_keeps_replaced_value() {
+        let domain = HazPtrDomain::new(&());
+        let map = HazHashMap::with_domain(&domain, 1);
+        let (created, dropped) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut hw = map.holder();
+        map.insert(1, CountDrops::new(42, &created, &dropped), &mut hw);
+
+        let mut h = map.holder();
+        let v = map.get(&1, &mut h).expect("inserted");
+        // the node v lives in is replaced and retired, but still protected by h.
+        let old = map.insert(1, CountDrops::new(43, &created, &dropped), &mut hw);
+        assert_eq!(old.map(CountDrops::val), Some(42));
+        drop(hw);
+        domain.eager_reclaim(false);
+        assert_eq!(v.val(), 42);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+        drop(h);
+        drop(map);
+        drop(domain);
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn grow_keeps_entries() {
+        let domain = HazPtrDomain::new(&());
+        let map = HazHashMap::with_domain(&domain, 1);
+        let (created, dropped) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut h = map.holder();
+        map.insert(0, CountDrops::new(0, &created, &dropped), &mut h);
+        let mut h0 = map.holder();
+        let zero = map.get(&0, &mut h0).expect("inserted");
+
+        for key in 1..1000 {
+            assert!(map.insert(key, CountDrops::new(key, &created, &dropped), &mut h).is_none());
+        }
+        assert!(buckets(&map) >= 1000 / MAX_LOAD);
+        domain.eager_reclaim(false);
+        // doubling links new dummies in between, the entries stay where they are.
+        assert_eq!(zero.val(), 0);
+        for key in 0..1000 {
+            assert_eq!(map.get(&key, &mut h).map(CountDrops::val), Some(key));
+        }
+        assert_eq!(map.len(), 1000);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+        drop((h, h0));
+        drop(map);
+        drop(domain);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "HazHashMap::get: holder belongs to HazPtrDomain")]
+    fn holder_of_other_domain() {
+        let dm = HazPtrDomain::new(&());
+        let dh = HazPtrDomain::new(&());
+        let map = HazHashMap::with_domain(&dm, 1);
+        map.insert(1, 1, &mut map.holder());
+        let _ = map.get(&1, &mut HazPtrHolder::for_domain(&dh));
+    }
+
+    #[test]
+    fn stress_no_leak_no_use_after_free() {
+        const THREADS: usize = 8;
+        const OPS: usize = if cfg!(miri) { 50 } else { 5_000 };
+        const KEYS: usize = 64;
+        let (created, dropped) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let domain = HazPtrDomain::new(&());
+        // one bucket, so the writers grow the table while the others read it.
+        let map = HazHashMap::with_domain(&domain, 1);
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let (map, created, dropped) = (&map, &created, &dropped);
+                s.spawn(move || {
+                    let mut h = map.holder();
+                    for i in 0..OPS {
+                        let key = (i * 7 + t) % KEYS;
+                        match (i + t) % 4 {
+                            0 => {
+                                let v = CountDrops::new(key, created, dropped);
+                                if let Some(old) = map.insert(key, v, &mut h) {
+                                    assert_eq!(old.val(), key);
+                                }
+                            }
+                            1 => {
+                                if let Some(old) = map.remove(&key, &mut h) {
+                                    assert_eq!(old.val(), key);
+                                }
+                            }
+                            2 => {
+                                let v = map.compute_if_absent(
+                                    key,
+                                    |&key| CountDrops::new(key, created, dropped),
+                                    &mut h,
+                                );
+                                assert_eq!(v.val(), key);
+                            }
+                            _ => {
+                                if let Some(v) = map.get(&key, &mut h) {
+                                    assert_eq!(v.val(), key);
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        assert!(buckets(&map) > 1);
+        drop(map);
+        drop(domain);
+        assert_eq!(
+            created.load(Ordering::SeqCst),
+            dropped.load(Ordering::SeqCst)
+        );
+    }
+}
@@ -0,0 +1,13 @@
+// The buildable part of these notes: the hazard pointer domain and the lock-free structures
+// on top of it, one crate so every file and its tests build once. The other .rs files at the
+// top level are standalone snippets, e.g. rustc --edition 2021 --test animal.rs.
+
+mod sync;
+
+pub mod haphazard_domain;
+pub mod haphazard_hashmap;
+pub mod treiber_stack;
+pub mod wait_free_simulator;
+
+#[cfg(all(test, not(loom)))]
+mod linearizability;
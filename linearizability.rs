@@ -0,0 +1,353 @@
+#![allow(dead_code)]
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+// Linearizability checker for histories of concurrent ops, test support for the lock-free
+// and wait-free structures.
+//
+// Threads run their ops through a Recorder, which ticks a shared clock right before the
+// invoke and right after the return. An op can only take effect in between.
+// check() then searches for a sequential order of all ops that respects real time, op a
+// before op b when a returned before b was invoked, and in which every op returns what the
+// sequential Spec says. Wing-Gong search: linearize any op invoked before every other pending
+// op returned, apply it to the spec state, backtrack on a wrong result. States already
+// failed for the same set of linearized ops are remembered and not searched again (Lowe).
+// P-compositionality: ops on different partitions of the spec, e.g. keys of a set, commute,
+// each partition is checked on its own.
+//
+// On failure, check() returns a minimal non-linearizable history: dropping any one op of it
+// makes it linearizable.
+
+// A sequential specification. apply() is the result op returns on this state, and the state
+// after it.
+pub trait Spec: Clone + Eq + Hash {
+    type Op: Clone + Debug;
+    type Ret: Clone + Debug + PartialEq;
+    fn apply(&self, op: &Self::Op) -> (Self::Ret, Self);
+    // ops of different partitions must commute and not see each other's effects.
+    fn partition(_op: &Self::Op) -> u64 {
+        0
+    }
+}
+
+// one completed op, invoke and returned are ticks of the recorder's clock.
+#[derive(Clone)]
+pub struct Event<S: Spec> {
+    pub thread: ThreadId,
+    pub op: S::Op,
+    pub ret: S::Ret,
+    pub invoke: u64,
+    pub returned: u64,
+}
+impl<S: Spec> Debug for Event<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>4}, {:>4}] {:?}: {:?} -> {:?}",
+            self.invoke, self.returned, self.thread, self.op, self.ret
+        )
+    }
+}
+
+pub struct Recorder<S: Spec> {
+    clock: AtomicU64,
+    events: Mutex<Vec<Event<S>>>,
+}
+impl<S: Spec> Recorder<S> {
+    pub fn new() -> Self {
+        Recorder {
+            clock: AtomicU64::new(0),
+            events: Mutex::new(Vec::new()),
+        }
+    }
+    // run op through f on the structure under test, and record it with what f returned.
+    pub fn record(&self, op: S::Op, f: impl FnOnce(&S::Op) -> S::Ret) -> S::Ret {
+        let invoke = self.clock.fetch_add(1, Ordering::SeqCst);
+        let ret = f(&op);
+        let returned = self.clock.fetch_add(1, Ordering::SeqCst);
+        self.events.lock().unwrap().push(Event {
+            thread: thread::current().id(),
+            op,
+            ret: ret.clone(),
+            invoke,
+            returned,
+        });
+        ret
+    }
+    pub fn history(self) -> Vec<Event<S>> {
+        self.events.into_inner().unwrap()
+    }
+}
+impl<S: Spec> Default for Recorder<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the ops of a history with no valid sequential order, sorted by invoke.
+pub struct NonLinearizable<S: Spec>(pub Vec<Event<S>>);
+impl<S: Spec> Debug for NonLinearizable<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "non-linearizable history, [invoke, return] thread: op -> ret")?;
+        for event in &self.0 {
+            writeln!(f, "  {:?}", event)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn check<S: Spec>(init: &S, history: &[Event<S>]) -> Result<(), NonLinearizable<S>> {
+    let mut partitions: BTreeMap<u64, Vec<&Event<S>>> = BTreeMap::new();
+    for event in history {
+        partitions.entry(S::partition(&event.op)).or_default().push(event);
+    }
+    for mut events in partitions.into_values() {
+        if !linearizable(init, &events) {
+            events.sort_by_key(|e| e.invoke);
+            return Err(NonLinearizable(minimize(init, events)));
+        }
+    }
+    Ok(())
+}
+pub fn assert_linearizable<S: Spec>(init: &S, history: &[Event<S>]) {
+    if let Err(failure) = check(init, history) {
+        panic!("{:?}", failure);
+    }
+}
+
+// drop ops one at a time while the rest stays non-linearizable.
+fn minimize<S: Spec>(init: &S, mut events: Vec<&Event<S>>) -> Vec<Event<S>> {
+    let mut i = 0;
+    while i < events.len() {
+        let dropped = events.remove(i);
+        if linearizable(init, &events) {
+            events.insert(i, dropped);
+            i += 1;
+        }
+    }
+    events.into_iter().cloned().collect()
+}
+
+// an op the search linearized, and the state it was applied to.
+struct Frame<S> {
+    state: S,
+    candidates: Vec<usize>,
+    next: usize,
+}
+fn linearizable<S: Spec>(init: &S, events: &[&Event<S>]) -> bool {
+    let n = events.len();
+    let mut linearized = vec![0u64; n.div_ceil(64)];
+    let mut count = 0;
+    let is_set = |bits: &[u64], i: usize| bits[i / 64] & (1 << (i % 64)) != 0;
+    // pending ops that may go next: invoked before the first return among pending ops.
+    let candidates = |bits: &[u64]| -> Vec<usize> {
+        let pending = (0..n).filter(|&i| !is_set(bits, i));
+        let first_return = pending.clone().map(|i| events[i].returned).min().unwrap_or(u64::MAX);
+        pending.filter(|&i| events[i].invoke < first_return).collect()
+    };
+    let mut failed: HashSet<(Vec<u64>, S)> = HashSet::new();
+    // iterative, histories may be longer than the stack is deep.
+    let mut stack = vec![Frame {
+        state: init.clone(),
+        candidates: candidates(&linearized),
+        next: 0,
+    }];
+    while count < n {
+        let Some(top) = stack.last_mut() else {
+            return false;
+        };
+        let Some(&i) = top.candidates.get(top.next) else {
+            // no op works next, undo the op that got us here.
+            let top = stack.pop().unwrap();
+            failed.insert((linearized.clone(), top.state));
+            if let Some(parent) = stack.last() {
+                let i = parent.candidates[parent.next - 1];
+                linearized[i / 64] &= !(1 << (i % 64));
+                count -= 1;
+            }
+            continue;
+        };
+        top.next += 1;
+        let (ret, state) = top.state.apply(&events[i].op);
+        if ret != events[i].ret {
+            continue;
+        }
+        linearized[i / 64] |= 1 << (i % 64);
+        count += 1;
+        if failed.contains(&(linearized.clone(), state.clone())) {
+            linearized[i / 64] &= !(1 << (i % 64));
+            count -= 1;
+            continue;
+        }
+        stack.push(Frame {
+            state,
+            candidates: candidates(&linearized),
+            next: 0,
+        });
+    }
+    true
+}
+
+// sequential specs of the crate's structures.
+
+#[derive(Clone, Debug)]
+pub enum StackOp<T> {
+    Push(T),
+    Pop,
+}
+// push returns None.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StackSpec<T>(pub Vec<T>);
+impl<T: Clone + Debug + Eq + Hash> Spec for StackSpec<T> {
+    type Op = StackOp<T>;
+    type Ret = Option<T>;
+    fn apply(&self, op: &StackOp<T>) -> (Option<T>, Self) {
+        let mut next = self.clone();
+        match op {
+            StackOp::Push(t) => {
+                next.0.push(t.clone());
+                (None, next)
+            }
+            StackOp::Pop => (next.0.pop(), next),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum QueueOp<T> {
+    Enqueue(T),
+    Dequeue,
+}
+// enqueue returns None.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QueueSpec<T>(pub VecDeque<T>);
+impl<T: Clone + Debug + Eq + Hash> Spec for QueueSpec<T> {
+    type Op = QueueOp<T>;
+    type Ret = Option<T>;
+    fn apply(&self, op: &QueueOp<T>) -> (Option<T>, Self) {
+        let mut next = self.clone();
+        match op {
+            QueueOp::Enqueue(t) => {
+                next.0.push_back(t.clone());
+                (None, next)
+            }
+            QueueOp::Dequeue => (next.0.pop_front(), next),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SetOp<T> {
+    Insert(T),
+    Remove(T),
+    Contains(T),
+}
+// insert and remove return whether they changed the set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SetSpec<T: Ord>(pub BTreeSet<T>);
+impl<T: Clone + Debug + Ord + Hash> Spec for SetSpec<T> {
+    type Op = SetOp<T>;
+    type Ret = bool;
+    fn apply(&self, op: &SetOp<T>) -> (bool, Self) {
+        let mut next = self.clone();
+        let ret = match op {
+            SetOp::Insert(t) => next.0.insert(t.clone()),
+            SetOp::Remove(t) => next.0.remove(t),
+            SetOp::Contains(t) => next.0.contains(t),
+        };
+        (ret, next)
+    }
+    // a key at a time.
+    fn partition(op: &SetOp<T>) -> u64 {
+        let (SetOp::Insert(t) | SetOp::Remove(t) | SetOp::Contains(t)) = op;
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event<S: Spec>(op: S::Op, ret: S::Ret, invoke: u64, returned: u64) -> Event<S> {
+        Event { thread: thread::current().id(), op, ret, invoke, returned }
+    }
+
+    #[test]
+    fn overlapping_ops_linearize_either_way() {
+        type S = QueueSpec<u8>;
+        // the dequeue overlaps the enqueue, it may or may not see its value.
+        for ret in [None, Some(1)] {
+            let history = [
+                event::<S>(QueueOp::Enqueue(1), None, 0, 3),
+                event::<S>(QueueOp::Dequeue, ret, 1, 2),
+            ];
+            assert!(check(&QueueSpec::default(), &history).is_ok());
+        }
+    }
+
+    #[test]
+    fn real_time_order_is_kept() {
+        type S = StackSpec<u8>;
+        // the pop returned before the push was invoked.
+        let history = [event::<S>(StackOp::Pop, Some(1), 0, 1), event::<S>(StackOp::Push(1), None, 2, 3)];
+        assert!(check(&StackSpec::default(), &history).is_err());
+        // lifo: 2 was pushed after 1, a later pop can not see 1 while 2 is there.
+        let history = [
+            event::<S>(StackOp::Push(1), None, 0, 1),
+            event::<S>(StackOp::Push(2), None, 2, 3),
+            event::<S>(StackOp::Pop, Some(1), 4, 5),
+        ];
+        assert!(check(&StackSpec::default(), &history).is_err());
+    }
+
+    #[test]
+    fn reports_minimal_history() {
+        type S = SetSpec<u8>;
+        let history = [
+            event::<S>(SetOp::Contains(1), false, 0, 1),
+            event::<S>(SetOp::Insert(1), true, 2, 3),
+            event::<S>(SetOp::Insert(2), true, 4, 5),
+            event::<S>(SetOp::Contains(2), true, 6, 7),
+            // nothing removed 1 in between.
+            event::<S>(SetOp::Insert(1), true, 8, 9),
+        ];
+        let NonLinearizable(minimal) = check(&SetSpec::default(), &history).unwrap_err();
+        // either insert alone is fine, key 2 and the contains do not matter.
+        let ops: Vec<_> = minimal.iter().map(|e| format!("{:?} -> {:?}", e.op, e.ret)).collect();
+        assert_eq!(ops, ["Insert(1) -> true", "Insert(1) -> true"]);
+    }
+
+    #[test]
+    fn records_threads() {
+        let recorder = Recorder::<QueueSpec<usize>>::new();
+        let queue = Mutex::new(VecDeque::new());
+        thread::scope(|s| {
+            for t in 0..4 {
+                let (recorder, queue) = (&recorder, &queue);
+                s.spawn(move || {
+                    for i in 0..25 {
+                        let op = if i % 2 == 0 { QueueOp::Enqueue(t * 100 + i) } else { QueueOp::Dequeue };
+                        recorder.record(op, |op| match op {
+                            QueueOp::Enqueue(v) => {
+                                queue.lock().unwrap().push_back(*v);
+                                None
+                            }
+                            QueueOp::Dequeue => queue.lock().unwrap().pop_front(),
+                        });
+                    }
+                });
+            }
+        });
+        let history = recorder.history();
+        assert_eq!(history.len(), 100);
+        assert_linearizable(&QueueSpec::default(), &history);
+    }
+}
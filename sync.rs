@@ -0,0 +1,119 @@
+// Atomics, Arc, threads and spin hints of the lock-free code. std's by default, loom's in
+// test builds under `--cfg loom`: loom::model runs a test once for every interleaving of the
+// atomic ops in it, and each thread only sees stores the memory model allows it to see. The
+// loom_tests modules next to the plain tests are only built then, e.g. with loom in the
+// offline cargo cache:
+//
+//   RUSTFLAGS="--cfg loom" cargo test --offline --release loom_tests
+//
+// loom is a dev-dependency, the library built for the test targets stays on std.
+//
+// LOOM_MAX_PREEMPTIONS bounds how many times a thread is switched out mid-op, model() below
+// defaults it to 2 so a run stays in seconds.
+//
+// loom's atomics have no const new and no get_mut, const_static!, loom_const_fn! and
+// WithMut paper over that. Statics holding a loom atomic are rebuilt per execution.
+#![allow(unused_imports, unused_macros)]
+
+#[cfg(not(all(test, loom)))]
+pub(crate) use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
+#[cfg(not(all(test, loom)))]
+pub(crate) use std::sync::Arc;
+#[cfg(not(all(test, loom)))]
+pub(crate) use std::{hint, thread, thread_local};
+
+#[cfg(all(test, loom))]
+pub(crate) use loom::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
+#[cfg(all(test, loom))]
+pub(crate) use loom::sync::Arc;
+#[cfg(all(test, loom))]
+pub(crate) use loom::{hint, thread};
+
+// loom's thread_local! takes no const { } initializer, strip it.
+#[cfg(all(test, loom))]
+macro_rules! loom_thread_local {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = const { $init:expr };) => {
+        loom::thread_local!($(#[$attr])* $vis static $name: $t = $init;);
+    };
+    ($($decl:tt)*) => {
+        loom::thread_local!($($decl)*);
+    };
+}
+#[cfg(all(test, loom))]
+pub(crate) use loom_thread_local as thread_local;
+
+pub(crate) use std::sync::atomic::Ordering;
+
+// &mut access to an atomic, e.g. in Drop. loom's have with_mut only, std's get it here.
+#[cfg(not(all(test, loom)))]
+pub(crate) trait WithMut<T> {
+    fn with_mut<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R;
+}
+#[cfg(not(all(test, loom)))]
+impl<T> WithMut<*mut T> for AtomicPtr<T> {
+    fn with_mut<R>(&mut self, f: impl FnOnce(&mut *mut T) -> R) -> R {
+        f(self.get_mut())
+    }
+}
+#[cfg(not(all(test, loom)))]
+impl WithMut<usize> for AtomicUsize {
+    fn with_mut<R>(&mut self, f: impl FnOnce(&mut usize) -> R) -> R {
+        f(self.get_mut())
+    }
+}
+
+// a static built in const, under loom a lazy_static! that every execution builds afresh
+// and drops at its end. Deref coerces either one to &T.
+macro_rules! const_static {
+    ($(#[$attr:meta])* static $name:ident: $t:ty = $init:expr;) => {
+        #[cfg(not(all(test, loom)))]
+        $(#[$attr])*
+        static $name: $t = $init;
+        #[cfg(all(test, loom))]
+        loom::lazy_static! {
+            $(#[$attr])*
+            static ref $name: $t = $init;
+        }
+    };
+}
+pub(crate) use const_static;
+
+// const fn with std's atomics, plain fn with loom's.
+macro_rules! loom_const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $name:ident $args:tt -> $ret:ty $body:block) => {
+        #[cfg(not(all(test, loom)))]
+        $(#[$attr])*
+        $vis const fn $name $args -> $ret $body
+        #[cfg(all(test, loom))]
+        $(#[$attr])*
+        $vis fn $name $args -> $ret $body
+    };
+}
+pub(crate) use loom_const_fn;
+
+// loom::model, bounded to a few preemptions per execution unless LOOM_MAX_PREEMPTIONS says
+// otherwise. Most bugs need only one or two, an unbounded run of a model takes hours.
+#[cfg(all(test, loom))]
+pub(crate) fn model(f: impl Fn() + Sync + Send + 'static) {
+    builder().check(f);
+}
+
+// model() for a model too big to explore in full, stops after max (or LOOM_MAX_DURATION)
+// with whatever interleavings it got through. Passing says less than model() does.
+#[cfg(all(test, loom))]
+pub(crate) fn model_for(max: std::time::Duration, f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = builder();
+    builder.max_duration.get_or_insert(max);
+    // loom looks at the clock every checkpoint_interval executions, 20_000 by default.
+    builder.checkpoint_interval = 100;
+    builder.check(f);
+}
+
+#[cfg(all(test, loom))]
+fn builder() -> loom::model::Builder {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound.get_or_insert(2);
+    // a slow path op of the simulator runs well over loom's default of 1000 atomic ops.
+    builder.max_branches = 100_000;
+    builder
+}
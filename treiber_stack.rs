@@ -1,6 +1,11 @@
-// crossbeam's lock-free algorithm: epoch based reclaimation.
+#![allow(dead_code)]
+
+// Treiber stack, lock-free with hazard pointer reclaimation from haphazard_domain.rs.
 // lock-free means no mutex, use atomic CAS to swap data/pointer when publishing.
 //
+// Originally on crossbeam's epoch based reclaimation, whose old API(cas_and_ref, cas_shared,
+// guard.unlinked) is long gone. Epoch pins a whole snapshot, hazptr protects just the head.
+//
 // Problem that one thread removes a *node* from a shared data structure
 // while other threads still holds the ref to the removed node.
 // 
@@ -9,8 +14,28 @@
 // 2. Subsequent reads of a lock-free data structure, the pointers it extracts
 // act like references with lifetime tied to the Guard. 
 // 
-// create Owned<T>, pin, get Shared<'a, T>, atomic swap Atomic<T>
-// cas_and_ref(&guard) or cas_shard();
+// crossbeam: create Owned<T>, pin, get Shared<'a, T>, atomic swap Atomic<T>
+// hazptr: box a HazPtrObjectWrapper<Node>, CAS head. Pop protects head with HazPtrHolder,
+// CAS head to head.next, and retires the popped node into the domain.
+// Protecting head also stops ABA, the node can not be freed and re-pushed while protected.
+//
+// Elimination backoff: the single head CAS serializes all pushes and pops. A push or pop
+// that loses the head CAS backs off into a random slot of the elimination array instead.
+// A push offers its node in an empty slot and waits a little; a pop that finds an offered node
+// CAS the slot to null and takes it, the pair cancels out without touching head.
+// Linearized as the push immediately followed by the pop, at the pop's slot CAS.
+//
+// Batch: push_all links a private chain and publishes it with one head CAS, pop_all swaps
+// head to null and owns the whole chain.
+//
+// Snapshot iter walks the live stack hand-over-hand with hazptrs of its own and yields copies.
+// A popped node's next is no good to walk on: the node below it may be popped and freed
+// before we protect it. Every pop marks the node it tries with an attempt before its head
+// CAS, so after protecting next, a node with no attempt, or still the head, proves next was
+// not popped yet, and so does the node before it. Otherwise walk again from head, skipping
+// down to the last node yielded; if that one is gone, so is everything yielded before it.
+// A pop moves its data out while an iterator may still read it, so iter wants T: Copy, a
+// Clone could run against a T its popper already changes or drops.
 //
 // the danger of atomic:
 // 1. memory access re-order by compiler/cpu, or slower in weakly-ordered 
@@ -26,76 +51,781 @@
 // 3. switch to atomic from mutex may surface bugs that depends on mutex 
 // memory order implicitly.
 
-use std::sync::atomic::Ordering::{Acquire, Release, Relaxed};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::mem::ManuallyDrop;
 use std::ptr;
 
-use crossbeam::mem::epoch::{self, Atomic, Owned};
+use crate::sync;
+use sync::Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst};
+#[cfg(not(all(test, loom)))]
+use sync::WithMut;
+use sync::{hint, thread, thread_local, AtomicPtr, AtomicUsize};
+
+use crate::haphazard_domain::{
+    deleters, Global, HazPtrArray, HazPtrDomain, HazPtrHolder, HazPtrObject, HazPtrObjectWrapper,
+};
+
+// data is moved out by the pop that unlinks the node, the retired node never drops it.
+// next is set before the node is published, never changes after.
+struct Node<'domain, T, F> {
+    data: ManuallyDrop<T>,
+    next: AtomicPtr<NodePtr<'domain, T, F>>,
+    state: AtomicUsize, // POPPED | pop attempts * POP_ATTEMPT, 0 while nobody pops it.
+}
+const POPPED: usize = 1;
+const POP_ATTEMPT: usize = 2;
+// the boxed node behind head and next, retired into the domain once popped.
+type NodePtr<'domain, T, F> = HazPtrObjectWrapper<'domain, Node<'domain, T, F>, F>;
+
+// how long an offered push waits in its elimination slot for a pop.
+const ELIMINATION_SPINS: usize = 128;
+
+// atomic cas swap next ptr when publishing/poping nodes.
+pub struct TreiberStack<'domain, T: 'domain, F: 'static> {
+    head: AtomicPtr<NodePtr<'domain, T, F>>,
+    // null or a node offered by a backed off push. Empty, no elimination.
+    elimination: Box<[AtomicPtr<NodePtr<'domain, T, F>>]>,
+    // pushed minus popped, up to date only when nobody pushes or pops.
+    len: AtomicUsize,
+    domain: &'domain HazPtrDomain<F>,
+    // owns Ts behind the AtomicPtrs, whose auto Send/Sync say nothing about T.
+    _owns: PhantomData<T>,
+}
+// Send: dropping the stack on another thread drops its Ts there.
+// Sync: push on one thread and pop on another moves T across, but &T is never shared, iter
+// only copies a T: Copy out. The domain is shared by all threads, F: Sync.
+unsafe impl<'domain, T: Send + 'domain, F: Sync + 'static> Send for TreiberStack<'domain, T, F> {}
+unsafe impl<'domain, T: Send + 'domain, F: Sync + 'static> Sync for TreiberStack<'domain, T, F> {}
+
+thread_local! {
+    // xorshift state for picking elimination slots, 0 until first use.
+    static ELIMINATION_RNG: Cell<u64> = const { Cell::new(0) };
+}
+fn random_slot(width: usize) -> usize {
+    ELIMINATION_RNG.with(|rng| {
+        let mut x = rng.get();
+        if x == 0 {
+            x = RandomState::new().hash_one(thread::current().id()) | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        rng.set(x);
+        x as usize % width
+    })
+}
 
-struct Node<T> {
-    data: T,
-    next: Atomic<Node<T>>,
+impl<T> TreiberStack<'static, T, Global> {
+  pub fn new() -> Self {
+    TreiberStack::with_domain(HazPtrDomain::global())
+  }
 }
-// atomic cas swap next ptr when publishing/poping nodes.  
-struct TreiberStack<T> {
-    head: Atomic<Node<T>>,
+impl<T> Default for TreiberStack<'static, T, Global> {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
-impl<T> TreiberStack<T> {
-  fn new() -> TreiberStack<T> {
+impl<'domain, T: 'domain, F: 'static> TreiberStack<'domain, T, F> {
+  pub fn with_domain(domain: &'domain HazPtrDomain<F>) -> Self {
+    TreiberStack::with_elimination(domain, 0)
+  }
+  // width elimination slots, 0 is the plain stack. A few slots per contending thread pair.
+  pub fn with_elimination(domain: &'domain HazPtrDomain<F>, width: usize) -> Self {
     TreiberStack {
-        head: Atomic::null()
+        head: AtomicPtr::new(ptr::null_mut()),
+        elimination: (0..width).map(|_| AtomicPtr::new(ptr::null_mut())).collect(),
+        len: AtomicUsize::new(0),
+        domain,
+        _owns: PhantomData,
     }
   }
 
-  fn push(&self, t: T) {
-    // create a locally owned node, before publishing 
-    let mut local_new_node = Owned::new(Node {
-      data: t,
-      next: Atomic::null(),
-    });
+  fn new_node(&self, t: T) -> *mut NodePtr<'domain, T, F> {
+    Box::into_raw(Box::new(HazPtrObjectWrapper::with_domain(
+      self.domain,
+      Node {
+        data: ManuallyDrop::new(t),
+        next: AtomicPtr::new(ptr::null_mut()),
+        state: AtomicUsize::new(0),
+      },
+    )))
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.head.load(Acquire).is_null()
+  }
+  // approximate while others push or pop, exact otherwise.
+  pub fn len(&self) -> usize {
+    self.len.load(Relaxed)
+  }
 
-    // pin the current epoch to get a guard of snapshot
-    let guard = epoch::pin();
+  pub fn push(&self, t: T) {
+    // create a locally owned node, before publishing
+    let local_new_node = self.new_node(t);
+    // count before publishing, so the pop that takes it can never make len wrap below 0.
+    self.len.fetch_add(1, Relaxed);
 
     loop {
-      // snapshot current head with Relaxed
-      let head = self.head.load(Relaxed, &guard);
+      // snapshot current head.
+      let head = self.head.load(Relaxed);
+
+      // update local owned's `next` to the snapshot, node not shared yet.
+      unsafe { &*local_new_node }.next.store(head, Relaxed);
+
+      // cas swap head to local owned node, Release, publishing data and next.
+      if self
+        .head
+        .compare_exchange_weak(head, local_new_node, Release, Relaxed)
+        .is_ok()
+      {
+        return;
+      }
+      // lost the cas to another thread, try to hand the node to a pop instead.
+      if self.eliminate_push(local_new_node) {
+        return;
+      }
+    }
+  }
+
+  // offer node in a slot, true when a pop took it. Otherwise node is still ours.
+  fn eliminate_push(&self, node: *mut NodePtr<'domain, T, F>) -> bool {
+    if self.elimination.is_empty() {
+      return false;
+    }
+    let slot = &self.elimination[random_slot(self.elimination.len())];
+    // Release, publishing data to the pop that takes the node.
+    if slot.compare_exchange(ptr::null_mut(), node, Release, Relaxed).is_err() {
+      return false;
+    }
+    // protect our offered node, so after a pop took and retired it the address can not be
+    // reused for another offer in this slot, and our withdraw cas below has no ABA.
+    // Safety: slot only ever holds boxed nodes, retired after taken out of the slot.
+    let mut holder = HazPtrHolder::for_domain(self.domain);
+    match unsafe { holder.load(slot) } {
+      Some(offered) if ptr::eq(offered, node) => {}
+      _ => return true, // taken before we protected it.
+    }
+    for _ in 0..ELIMINATION_SPINS {
+      if slot.load(Relaxed) != node {
+        return true;
+      }
+      hint::spin_loop();
+    }
+    // nobody came, withdraw the offer. Failed means a pop just took it.
+    slot.compare_exchange(node, ptr::null_mut(), Relaxed, Relaxed).is_err()
+  }
+
+  // take a node offered by a push, if the random slot has one.
+  fn eliminate_pop(&self) -> Option<T> {
+    if self.elimination.is_empty() {
+      return None;
+    }
+    let slot = &self.elimination[random_slot(self.elimination.len())];
+    let node = slot.load(Relaxed);
+    if node.is_null() {
+      return None;
+    }
+    // the pusher never touches an offered node's data again. Whoever cas it out of the slot
+    // owns it, an ABA'd node is just another offer. Acquire the pusher's data.
+    let node = slot.compare_exchange(node, ptr::null_mut(), Acquire, Relaxed).ok()?;
+    self.len.fetch_sub(1, Relaxed);
+    // the pusher may still protect it, retire rather than free.
+    Some(unsafe { Self::take_popped(node) })
+  }
 
-      // update local owned's `next` pointer to shared data from snapshot
-      local_new_node.next.store_shared(head, Relaxed);
+  pub fn pop(&self) -> Option<T> {
+    // grab a hazptr from the domain, it guards one head at a time.
+    let mut holder = HazPtrHolder::for_domain(self.domain);
 
-      // cas swap head's next to local owned node, Release, publishing. 
-      match self.head.cas_and_ref(head, local_new_node, Release, &guard) {
-        Ok(_) => return,
-        Err(owned) => local_new_node = owned,
+    loop {
+      // protect head into the hazptr with Acquire, None when we observed the stack empty.
+      // Safety: nodes are boxed by push, and only retired into the domain after unlinked.
+      let head = unsafe { holder.load(&self.head) }?;
+      // head is protected, nobody can free it, read is *safely*!
+      let next = head.next.load(Relaxed);
+      // tell iterators head may be going, before next can become the head.
+      head.state.fetch_add(POP_ATTEMPT, SeqCst);
+      let head_ptr = head as *const NodePtr<'domain, T, F> as *mut NodePtr<'domain, T, F>;
+      // cas swap head with head's next. The protected head can not be freed and re-pushed,
+      // so a successful cas really unlinked our head, no ABA.
+      if let Ok(head) = self.head.compare_exchange(head_ptr, next, AcqRel, Relaxed) {
+        self.len.fetch_sub(1, Relaxed);
+        // the unlinked node, as the Box ptr push published, is now ours.
+        return Some(unsafe { Self::take_popped(head) });
+      }
+      head.state.fetch_sub(POP_ATTEMPT, SeqCst);
+      // lost the cas to another thread, try to take a node straight from a push.
+      if let Some(data) = self.eliminate_pop() {
+        return Some(data);
       }
     }
   }
 
-  fn pop(&self) -> Option<T> {
-    // pin the current epoch to get a guard of the snapshot
-    let guard = epoch::pin();
+  // move data out of a node we unlinked, and retire the node.
+  // Safety: node is unlinked by the caller, nobody else takes or retires it.
+  unsafe fn take_popped(node: *mut NodePtr<'domain, T, F>) -> T {
+    let n = unsafe { &*node };
+    // iterators standing on the node walk no further down from it.
+    n.state.fetch_or(POPPED, SeqCst);
+    // only the winner of the cas moves out the data from the now-unlinked node
+    let data = unsafe { ptr::read(&*n.data) };
+    // iterators still protecting the node are done before the domain frees it.
+    unsafe { (*node).retire(deleters::_drop_box) };
+    data
+  }
 
+  // push items with one head cas, the last item ends up on top, as if pushed one by one.
+  pub fn push_all<I: IntoIterator<Item = T>>(&self, items: I) {
+    let mut items = items.into_iter();
+    let Some(first) = items.next() else {
+      return;
+    };
+    // link a private chain, first item at the bottom.
+    let bottom = self.new_node(first);
+    let mut top = bottom;
+    let mut n = 1;
+    for t in items {
+      let node = self.new_node(t);
+      unsafe { &*node }.next.store(top, Relaxed);
+      top = node;
+      n += 1;
+    }
+    self.len.fetch_add(n, Relaxed);
     loop {
-      // take a snapshot with Acquire
-      match self.head.load(Acquire, &guard) {
-        // the stack is non-empty
-        Some(head) => {
-          // head is shared ptr in guarded snapshot, read is *safely*!
-          let next = head.next.load(Relaxed, &guard);
-          // cas swap head with head's next.
-          if self.head.cas_shared(Some(head), next, Release) {
-            unsafe {
-              // mark the node as unlinked in the guarded snapshot.
-              guard.unlinked(head);
-              // extract out the data from the now-unlinked node
-              return Some(ptr::read(&(*head).data))
-            }
-          }
+      let head = self.head.load(Relaxed);
+      unsafe { &*bottom }.next.store(head, Relaxed);
+      // Release publishes the whole chain.
+      if self.head.compare_exchange_weak(head, top, Release, Relaxed).is_ok() {
+        return;
+      }
+    }
+  }
+
+  // detach the whole stack at once, top first. Items not iterated are dropped with PopAll.
+  pub fn pop_all(&self) -> PopAll<'domain, T, F> {
+    // swap, not cas, the chain is ours no matter what head was, no ABA, no protection needed.
+    let head = self.head.swap(ptr::null_mut(), Acquire);
+    // the chain is ours, walk it to take its length off at once.
+    let mut n = 0;
+    let mut node = head;
+    while !node.is_null() {
+      n += 1;
+      node = unsafe { &*node }.next.load(Relaxed);
+    }
+    self.len.fetch_sub(n, Relaxed);
+    PopAll { head }
+  }
+}
+
+// &mut self, no pop or iter is in flight, and nobody else can protect our nodes any more.
+// Free them directly, dropping the data no pop moved out.
+impl<'domain, T: 'domain, F: 'static> Drop for TreiberStack<'domain, T, F> {
+  fn drop(&mut self) {
+    // every push returned, its offer was either taken or withdrawn.
+    debug_assert!(self.elimination.iter_mut().all(|slot| slot.with_mut(|offer| offer.is_null())));
+    let mut node = self.head.with_mut(|head| *head);
+    while !node.is_null() {
+      let mut boxed = unsafe { Box::from_raw(node) };
+      node = boxed.next.with_mut(|next| *next);
+      unsafe { ManuallyDrop::drop(&mut boxed.data) };
+    }
+  }
+}
+
+pub struct PopAll<'domain, T: 'domain, F: 'static> {
+  head: *mut NodePtr<'domain, T, F>,
+}
+// owns the detached chain, moves and drops Ts and retires nodes into the shared domain.
+unsafe impl<'domain, T: Send + 'domain, F: Sync + 'static> Send for PopAll<'domain, T, F> {}
+
+impl<'domain, T: 'domain, F: 'static> Iterator for PopAll<'domain, T, F> {
+  type Item = T;
+  fn next(&mut self) -> Option<T> {
+    if self.head.is_null() {
+      return None;
+    }
+    let node = self.head;
+    // top first, so a node is marked popped before any node below it is retired.
+    self.head = unsafe { &*node }.next.load(Relaxed);
+    Some(unsafe { TreiberStack::take_popped(node) })
+  }
+}
+
+impl<'domain, T: 'domain, F: 'static> Drop for PopAll<'domain, T, F> {
+  fn drop(&mut self) {
+    self.for_each(drop);
+  }
+}
+
+// copies T on this thread while a pop may be moving the same T out, T: Copy.
+impl<'domain, T: Copy + 'domain, F: 'static> TreiberStack<'domain, T, F> {
+  // weakly consistent: yields every element that stays on the stack for the whole walk, top
+  // first, none twice. Elements pushed or popped meanwhile may or may not show up.
+  pub fn iter(&self) -> Iter<'_, 'domain, T, F> {
+    let mut iter = Iter {
+      stack: self,
+      hazptrs: HazPtrArray::for_domain(self.domain),
+      prev: ptr::null(),
+      cur: ptr::null(),
+      resume: ptr::null(),
+    };
+    iter.restart();
+    iter
+  }
+}
+
+// hazptrs[0] protects prev, [1] cur, [2] the next node while we validate it.
+pub struct Iter<'s, 'domain, T: 'domain, F: 'static> {
+  stack: &'s TreiberStack<'domain, T, F>,
+  hazptrs: HazPtrArray<'domain, F, 3>,
+  prev: *const NodePtr<'domain, T, F>, // the node we came from, null at head.
+  cur: *const NodePtr<'domain, T, F>,
+  // after a restart, the last node yielded: skip down to it, the nodes above it were yielded
+  // or pushed meanwhile. Null while every node is new to us. Compared by address only.
+  resume: *const NodePtr<'domain, T, F>,
+}
+
+impl<'domain, T: 'domain, F: 'static> Iter<'_, 'domain, T, F> {
+  fn restart(&mut self) {
+    self.prev = ptr::null();
+    // Safety: head only holds boxed nodes, retired after unlinked.
+    self.cur = unsafe { self.hazptrs.load(1, &self.stack.head) }.map_or(ptr::null(), |n| n as *const _);
+  }
+  // node was on the stack right now, so was everything below it. A pop still trying node may
+  // win any moment after, no wait.
+  fn linked(&self, node: *const NodePtr<'domain, T, F>) -> bool {
+    // Safety: a protected node, or null.
+    let Some(n) = (unsafe { node.as_ref() }) else {
+      return false;
+    };
+    let state = n.state.load(SeqCst);
+    state == 0 || (state & POPPED == 0 && ptr::eq(self.stack.head.load(SeqCst), node))
+  }
+  // move cur to the next node. When cur may be unlinked, next may be freed already, walk
+  // again from head instead.
+  fn advance(&mut self) {
+    // Safety: cur is protected by hazptrs[1]; next and head only hold boxed nodes.
+    let cur = unsafe { &*self.cur };
+    // no deref before the check below.
+    let next = self.hazptrs.protect_raw(2, &cur.next);
+    if self.linked(self.cur) || self.linked(self.prev) {
+      // prev <- cur <- next, the old prev slot is free for the following node.
+      self.hazptrs.swap(0, 1);
+      self.hazptrs.swap(1, 2);
+      self.prev = self.cur;
+      self.cur = next;
+      return;
+    }
+    // while skipping, resume stays. Else cur is the last yielded, unless it is popped, then
+    // so is everything we yielded.
+    if self.resume.is_null() && cur.state.load(SeqCst) & POPPED == 0 {
+      self.resume = self.cur;
+    }
+    self.restart();
+  }
+}
+
+impl<'domain, T: Copy + 'domain, F: 'static> Iterator for Iter<'_, 'domain, T, F> {
+  type Item = T;
+  fn next(&mut self) -> Option<T> {
+    loop {
+      if self.cur.is_null() {
+        if self.resume.is_null() {
+          return None;
+        }
+        // walked down without meeting resume: it got popped, and all we yielded before it.
+        self.resume = ptr::null();
+        self.restart();
+        continue;
+      }
+      let data = if self.resume.is_null() {
+        // Safety: protected by hazptrs[1].
+        Some(*unsafe { &*self.cur }.data)
+      } else {
+        if ptr::eq(self.cur, self.resume) {
+          self.resume = ptr::null();
         }
-        // we observed the stack empty
-        None => return None
+        None
+      };
+      self.advance();
+      if data.is_some() {
+        return data;
       }
     }
   }
 }
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::linearizability::{assert_linearizable, Recorder, StackOp, StackSpec};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // each value counts its drops in its own slot.
+    struct CountDrops<'a>(usize, &'a [AtomicUsize]);
+    impl Drop for CountDrops<'_> {
+        fn drop(&mut self) {
+            self.1[self.0].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn push_pop_lifo() {
+        let domain = HazPtrDomain::new(&());
+        let stack = TreiberStack::with_domain(&domain);
+        assert_eq!(stack.pop(), None);
+        for i in 0..3 {
+            stack.push(i);
+        }
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.pop(), Some(2));
+        stack.push(3);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack.pop(), Some(0));
+        assert_eq!(stack.pop(), None);
+        assert_eq!(stack.len(), 0);
+    }
+
+    #[test]
+    fn drop_frees_remaining() {
+        let drops: Vec<AtomicUsize> = (0..4).map(|_| AtomicUsize::new(0)).collect();
+        let domain = HazPtrDomain::new(&());
+        let stack = TreiberStack::with_domain(&domain);
+        for i in 0..4 {
+            stack.push(CountDrops(i, &drops));
+        }
+        drop(stack.pop());
+        assert_eq!(drops[3].load(Ordering::SeqCst), 1);
+        drop(stack);
+        assert!(drops.iter().all(|n| n.load(Ordering::SeqCst) == 1));
+    }
+
+    // T: Send is enough to share the stack, only iter needs T: Sync.
+    #[test]
+    fn send_sync() {
+        fn send_sync<S: Send + Sync>() {}
+        send_sync::<TreiberStack<'static, Cell<usize>, Global>>();
+        send_sync::<TreiberStack<'static, String, Global>>();
+        fn send<S: Send>() {}
+        send::<PopAll<'static, String, Global>>();
+    }
+
+    #[test]
+    fn threads_pop_each_push_once() {
+        threads_pop_each_push_once_with(0);
+    }
+
+    #[test]
+    fn elimination_pops_each_push_once() {
+        threads_pop_each_push_once_with(2);
+    }
+
+    // with and without elimination, a pop taking an offer must still look like a pop of the head.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 3;
+        const OPS: usize = if cfg!(miri) { 6 } else { 16 };
+        const ROUNDS: usize = if cfg!(miri) { 1 } else { 20 };
+        for round in 0..ROUNDS {
+            let domain = HazPtrDomain::new(&());
+            let stack = TreiberStack::with_elimination(&domain, round % 2 * 2);
+            let recorder = Recorder::<StackSpec<usize>>::new();
+            std::thread::scope(|s| {
+                for t in 0..THREADS {
+                    let (stack, recorder) = (&stack, &recorder);
+                    s.spawn(move || {
+                        for i in 0..OPS {
+                            let op = if (i + t + round) % 2 == 0 {
+                                StackOp::Push(t * OPS + i)
+                            } else {
+                                StackOp::Pop
+                            };
+                            recorder.record(op, |op| match *op {
+                                StackOp::Push(v) => {
+                                    stack.push(v);
+                                    None
+                                }
+                                StackOp::Pop => stack.pop(),
+                            });
+                        }
+                    });
+                }
+            });
+            assert_linearizable(&StackSpec::default(), &recorder.history());
+        }
+    }
+
+    #[test]
+    fn push_all_pop_all() {
+        let domain = HazPtrDomain::new(&());
+        let stack = TreiberStack::with_domain(&domain);
+        assert!(stack.is_empty());
+        stack.push_all(Vec::<usize>::new());
+        assert!(stack.is_empty());
+        stack.push(0);
+        stack.push_all(1..4);
+        assert!(!stack.is_empty());
+        assert_eq!(stack.iter().collect::<Vec<_>>(), [3, 2, 1, 0]);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.len(), 3);
+        let all = stack.pop_all();
+        assert_eq!(stack.len(), 0);
+        assert_eq!(all.collect::<Vec<_>>(), [2, 1, 0]);
+        assert!(stack.is_empty());
+        assert_eq!(stack.iter().next(), None);
+
+        // a partly consumed pop_all drops the rest.
+        let drops: Vec<AtomicUsize> = (0..3).map(|_| AtomicUsize::new(0)).collect();
+        let stack = TreiberStack::with_domain(&domain);
+        stack.push_all((0..3).map(|i| CountDrops(i, &drops)));
+        let mut all = stack.pop_all();
+        assert_eq!(all.next().map(|v| v.0), Some(2));
+        drop(all);
+        assert!(drops.iter().all(|n| n.load(Ordering::SeqCst) == 1));
+    }
+
+    // iterators see no element twice and every element never popped, while others pop.
+    #[test]
+    fn iter_while_popping() {
+        const N: usize = if cfg!(miri) { 20 } else { 2_000 };
+        const WALKS: usize = if cfg!(miri) { 2 } else { 10 };
+        let domain = HazPtrDomain::new(&());
+        let stack = TreiberStack::with_domain(&domain);
+        // 0..N stay, N..3N come and go.
+        stack.push_all(0..N);
+        stack.push_all(N..3 * N);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..N {
+                    let v = stack.pop().expect("not empty");
+                    assert!(v >= N);
+                    stack.push(v);
+                    stack.pop();
+                }
+            });
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..WALKS {
+                        let mut seen = vec![false; 3 * N];
+                        for v in stack.iter() {
+                            assert!(!std::mem::replace(&mut seen[v], true), "{v} twice");
+                        }
+                        assert!(seen[..N].iter().all(|&s| s));
+                    }
+                });
+            }
+        });
+    }
+
+    // one cpu rarely loses a head cas, offer by hand.
+    #[test]
+    fn eliminate_pop_takes_offer() {
+        let domain = HazPtrDomain::new(&());
+        let stack = TreiberStack::with_elimination(&domain, 1);
+        assert_eq!(stack.eliminate_pop(), None);
+        // as push would, count before offering.
+        stack.len.fetch_add(1, Ordering::SeqCst);
+        stack.elimination[0].store(stack.new_node(7), Ordering::SeqCst);
+        assert_eq!(stack.eliminate_pop(), Some(7));
+        assert_eq!(stack.len(), 0);
+        assert!(stack.elimination[0].load(Ordering::SeqCst).is_null());
+        // an offer nobody takes is withdrawn, the node is still ours.
+        let node = stack.new_node(8);
+        assert!(!stack.eliminate_push(node));
+        assert!(stack.elimination[0].load(Ordering::SeqCst).is_null());
+        drop(unsafe { Box::from_raw(node) });
+    }
+
+    fn threads_pop_each_push_once_with(width: usize) {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = if cfg!(miri) { 20 } else { 2_000 };
+        const TOTAL: usize = THREADS * PER_THREAD;
+        let drops: Vec<AtomicUsize> = (0..TOTAL).map(|_| AtomicUsize::new(0)).collect();
+        let popped: Vec<AtomicUsize> = (0..TOTAL).map(|_| AtomicUsize::new(0)).collect();
+        let npopped = AtomicUsize::new(0);
+        let domain = HazPtrDomain::new(&());
+        let stack = TreiberStack::with_elimination(&domain, width);
+
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let (stack, drops) = (&stack, &drops);
+                s.spawn(move || {
+                    for i in t * PER_THREAD..(t + 1) * PER_THREAD {
+                        stack.push(CountDrops(i, drops));
+                    }
+                });
+            }
+            for _ in 0..THREADS {
+                let (stack, popped, npopped) = (&stack, &popped, &npopped);
+                s.spawn(move || {
+                    while npopped.load(Ordering::SeqCst) < TOTAL {
+                        if let Some(v) = stack.pop() {
+                            popped[v.0].fetch_add(1, Ordering::SeqCst);
+                            npopped.fetch_add(1, Ordering::SeqCst);
+                        } else {
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+        });
+        assert!(stack.pop().is_none());
+        assert_eq!(stack.len(), 0);
+        drop(stack);
+        drop(domain);
+        assert!(popped.iter().all(|n| n.load(Ordering::SeqCst) == 1));
+        assert!(drops.iter().all(|n| n.load(Ordering::SeqCst) == 1));
+    }
+
+    // cargo test -- --ignored --nocapture bench_elimination
+    #[test]
+    #[ignore]
+    fn bench_elimination() {
+        const OPS: usize = 100_000;
+        for threads in [2, 4, 8, 16, 32] {
+            for width in [0, 4, 16] {
+                let domain = HazPtrDomain::new(&());
+                let stack = TreiberStack::with_elimination(&domain, width);
+                let start = std::time::Instant::now();
+                std::thread::scope(|s| {
+                    for t in 0..threads {
+                        let stack = &stack;
+                        // half the threads push, half pop.
+                        s.spawn(move || {
+                            for i in 0..OPS {
+                                if t % 2 == 0 {
+                                    stack.push(i);
+                                } else {
+                                    std::hint::black_box(stack.pop());
+                                }
+                            }
+                        });
+                    }
+                });
+                let elapsed = start.elapsed();
+                while stack.pop().is_some() {}
+                println!(
+                    "{:>2} threads, {:>2} elimination slots: {:.1} Mops/s",
+                    threads,
+                    width,
+                    (threads * OPS) as f64 / elapsed.as_secs_f64() / 1e6
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use sync::Arc;
+
+    // each thread pushes one value and pops one. Every value comes out exactly once, and a
+    // pop that races a push either sees its node whole or not at all.
+    #[test]
+    fn push_pop() {
+        sync::model(|| {
+            let stack = Arc::new(TreiberStack::new());
+            let threads: Vec<_> = (0..2)
+                .map(|t| {
+                    let stack = Arc::clone(&stack);
+                    thread::spawn(move || {
+                        stack.push(t);
+                        let popped = stack.pop().expect("our own push at least");
+                        // retire alone waits for the threshold, reclaim against the other pop.
+                        HazPtrDomain::global().eager_reclaim(false);
+                        popped
+                    })
+                })
+                .collect();
+            let mut popped: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+            popped.sort_unstable();
+            assert_eq!(popped, [0, 1]);
+            assert!(stack.pop().is_none());
+        });
+    }
+
+    // two pops race on the same head: one unlinks it, the other retries on the next node.
+    // The loser may still protect the winner's node, the domain frees it only after.
+    #[test]
+    fn pop_pop() {
+        sync::model(|| {
+            let stack = Arc::new(TreiberStack::new());
+            stack.push(0);
+            stack.push(1);
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let stack = Arc::clone(&stack);
+                    thread::spawn(move || {
+                        let popped = stack.pop();
+                        HazPtrDomain::global().eager_reclaim(false);
+                        popped
+                    })
+                })
+                .collect();
+            let mut popped: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap().unwrap()).collect();
+            popped.sort_unstable();
+            assert_eq!(popped, [0, 1]);
+            assert_eq!(stack.len(), 0);
+        });
+    }
+
+    // a pop unlinks the node an iterator stands on. Neither waits for the other, the walk still
+    // yields what stays, once.
+    #[test]
+    fn iter_pop() {
+        sync::model(|| {
+            let stack = Arc::new(TreiberStack::new());
+            stack.push_all([0, 1]);
+            let popper = {
+                let stack = Arc::clone(&stack);
+                thread::spawn(move || {
+                    let popped = stack.pop();
+                    HazPtrDomain::global().eager_reclaim(false);
+                    popped
+                })
+            };
+            let seen: Vec<usize> = stack.iter().collect();
+            assert_eq!(popper.join().unwrap(), Some(1));
+            assert!(seen == [1, 0] || seen == [0], "{seen:?}");
+            HazPtrDomain::global().eager_reclaim(false);
+        });
+    }
+
+    // a pop that loses to a push leaves a stale attempt on the old head, the walk restarts
+    // and must neither repeat 1 nor miss 0.
+    #[test]
+    fn iter_push_pop() {
+        sync::model(|| {
+            let stack = Arc::new(TreiberStack::new());
+            stack.push_all([0, 1]);
+            let threads: Vec<_> = [None, Some(2)]
+                .into_iter()
+                .map(|push| {
+                    let stack = Arc::clone(&stack);
+                    thread::spawn(move || match push {
+                        Some(v) => stack.push(v),
+                        None => drop(stack.pop()),
+                    })
+                })
+                .collect();
+            let seen: Vec<usize> = stack.iter().collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            let mut sorted = seen.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), seen.len(), "{seen:?}");
+            assert_eq!(seen.last(), Some(&0), "{seen:?}");
+            drop(stack.pop_all());
+            HazPtrDomain::global().eager_reclaim(false);
+        });
+    }
+}
@@ -1,16 +1,64 @@
+#![allow(dead_code)]
+
+
 use std::ops::Index;
-use std::marker::PhantomData;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::Ordering;
-use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize};
-use std::thread;
+use std::ptr;
+use crate::sync;
+#[cfg(not(all(test, loom)))]
+use sync::WithMut;
+use sync::{const_static, thread, thread_local, Arc, Ordering};
+use sync::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
 
+use crate::haphazard_domain::{deleters, HazPtrArray, HazPtrDomain, HazPtrHolder, HazPtrObject};
+
+// default contention a fast path op tolerates before it goes down the slow path.
 pub const RETRY_THRESHOLD: usize = 10;
-pub struct ContentionMeasure(usize);
+// by default a thread helps the front of the help queue before each of its ops.
+pub const HELP_EVERY: usize = 1;
+// Counts the contention one fast path op runs into: failed cases and the retries a
+// generator does on its own, e.g. a failed snip in a search. Past the threshold of the
+// simulator, detected() says so and the op gives up on the fast path.
+pub struct ContentionMeasure {
+    count: usize,
+    threshold: usize,
+}
+#[derive(Debug)]
 pub struct Contention;
+impl ContentionMeasure {
+    pub fn new(threshold: usize) -> Self {
+        ContentionMeasure { count: 0, threshold }
+    }
+    // helpers on the slow path never give up.
+    pub fn unbounded() -> Self {
+        ContentionMeasure::new(usize::MAX)
+    }
+    // generators call it on every retry, and bail out with `?` on Err.
+    pub fn detected(&mut self) -> Result<(), Contention> {
+        self.count = self.count.saturating_add(1);
+        if self.count > self.threshold {
+            Err(Contention)
+        } else {
+            Ok(())
+        }
+    }
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
 
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CasState {
+    Pending,
+    Success,
+    Failure,
+}
+// Helpers of a slow path op all execute the same CasDescriptor, execute must perform its cas
+// at most once and return the same outcome to every one of them, see CasDesc.
 pub trait CasDescriptor {
+    fn state(&self) -> CasState;
+    // Err when the cas found another value, nothing more to say.
+    #[allow(clippy::result_unit_err)]
     fn execute(&self) -> Result<(), ()>;
 }
 // bound to Index trait with usize Idx and Output=D bounds to CasDescriptor.
@@ -18,6 +66,258 @@ pub trait CasDescriptors<D>: Index<usize, Output = D>
 where D: CasDescriptor,
 {
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// A word a CasDesc can target, an AtomicPtr<T> or an AtomicUsize.
+// One bit of the word is reserved: while a CasDesc executes, the target briefly holds a
+// CasAttempt tagged by that bit. Read targets through CasTarget::load, it finishes such an
+// attempt first. Pointees of AtomicPtr targets are aligned to at least 2, AtomicUsize
+// targets keep their values below the top bit.
+pub trait CasTarget: Sync {
+    type Value: Copy + Eq;
+    fn word(&self) -> &AtomicUsize;
+    fn to_word(v: Self::Value) -> usize;
+    fn from_word(w: usize) -> Self::Value;
+    // tagged word of a CasAttempt, and back. None when w is a plain value.
+    fn attempt_word(attempt: *mut CasAttempt) -> usize;
+    fn word_attempt(w: usize) -> Option<*mut CasAttempt>;
+
+    fn load(&self) -> Self::Value {
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        loop {
+            // Safety: attempts are Box::into_raw ptrs retired into HELP_DOMAIN once unlinked.
+            let (w, attempt) = unsafe {
+                holder.load_word(self.word(), |w| Self::word_attempt(w).unwrap_or(ptr::null_mut()))
+            };
+            match attempt {
+                Some(attempt) => attempt.finish(self.word(), w),
+                None => return Self::from_word(w),
+            }
+        }
+    }
+}
+impl<T> CasTarget for AtomicPtr<T> {
+    type Value = *mut T;
+    fn word(&self) -> &AtomicUsize {
+        // Safety: AtomicPtr and AtomicUsize have the same size and alignment.
+        #[cfg(not(all(test, loom)))]
+        return unsafe { AtomicUsize::from_ptr(self.as_ptr() as *mut usize) };
+        // Safety: loom's are both a handle to a model object that keeps any value as a u64.
+        #[cfg(all(test, loom))]
+        return unsafe { &*(self as *const Self as *const AtomicUsize) };
+    }
+    fn to_word(v: *mut T) -> usize {
+        assert!(v as usize & 1 == 0, "CasTarget pointees are aligned to 2");
+        v as usize
+    }
+    fn from_word(w: usize) -> *mut T {
+        w as *mut T
+    }
+    fn attempt_word(attempt: *mut CasAttempt) -> usize {
+        attempt as usize | 1
+    }
+    fn word_attempt(w: usize) -> Option<*mut CasAttempt> {
+        (w & 1 == 1).then_some((w & !1) as *mut CasAttempt)
+    }
+}
+const USIZE_TAG: usize = 1 << (usize::BITS - 1);
+impl CasTarget for AtomicUsize {
+    type Value = usize;
+    fn word(&self) -> &AtomicUsize {
+        self
+    }
+    fn to_word(v: usize) -> usize {
+        assert!(v & USIZE_TAG == 0, "CasTarget values stay below the top bit");
+        v
+    }
+    fn from_word(w: usize) -> usize {
+        w
+    }
+    // attempts are aligned, the low bit is free to make room for the tag.
+    fn attempt_word(attempt: *mut CasAttempt) -> usize {
+        (attempt as usize >> 1) | USIZE_TAG
+    }
+    fn word_attempt(w: usize) -> Option<*mut CasAttempt> {
+        (w & USIZE_TAG != 0).then_some(((w & !USIZE_TAG) << 1) as *mut CasAttempt)
+    }
+}
+
+// decision of a CasDesc, shared by its clones: PENDING, FAILURE, or the number of the
+// attempt that won. A number, not the attempt's address: the winner is freed once unlinked
+// while the decision lives on, and a later attempt may get the same address.
+const CAS_PENDING: usize = 0;
+const CAS_FAILURE: usize = 1;
+struct Decision {
+    outcome: AtomicUsize,
+    // hands out attempt numbers, from FAILURE + 1 up.
+    attempts: AtomicUsize,
+}
+
+// One try of a helper to perform a CasDesc, RDCSS style:
+// 1. cas the target from expected to the tagged attempt.
+// 2. decide: cas the desc decision from PENDING to this attempt.
+// 3. cas the target from the attempt to new if this attempt won, back to expected if not.
+// Anyone who meets an attempt in the target runs 2 and 3 for it, so a stalled helper never
+// blocks the target. A stale helper whose attempt lands after the desc was decided, even
+// when the target went back to expected meanwhile, loses step 2 and undoes itself: the cas
+// takes effect once, when the winning attempt decides.
+pub struct CasAttempt {
+    decision: Arc<Decision>,
+    number: usize,
+    expected: usize,
+    new: usize,
+}
+impl<'d> HazPtrObject<'d, HelpQueueFamily> for CasAttempt {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+impl CasAttempt {
+    // steps 2 and 3, w is the tagged word of self, protected by the caller.
+    fn finish(&self, target: &AtomicUsize, w: usize) {
+        let won = match self.decision.outcome.compare_exchange(
+            CAS_PENDING,
+            self.number,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => true,
+            Err(decided) => decided == self.number,
+        };
+        let to = if won { self.new } else { self.expected };
+        if target.compare_exchange(w, to, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            // unlinked by us, retired by us.
+            unsafe { retire(self as *const CasAttempt as *mut CasAttempt) };
+        }
+    }
+}
+
+// A cas on target from expected to new, executed at most once however many helpers run it.
+// Clones share the decision, so all helpers of a record see the same outcome.
+pub struct CasDesc<A: CasTarget> {
+    target: *const A,
+    expected: A::Value,
+    new: A::Value,
+    decision: Arc<Decision>,
+}
+// Safety: target is Sync, the decision an Arc of atomics.
+unsafe impl<A: CasTarget> Send for CasDesc<A> where A::Value: Send {}
+unsafe impl<A: CasTarget> Sync for CasDesc<A> where A::Value: Sync {}
+impl<A: CasTarget> CasDesc<A> {
+    /// # Safety
+    ///
+    /// target outlives every execute of the desc and of its clones. Normalized algorithms
+    /// target their own fields, and execute only runs while the simulator is alive.
+    pub unsafe fn new(target: &A, expected: A::Value, new: A::Value) -> Self {
+        CasDesc {
+            target,
+            expected,
+            new,
+            decision: Arc::new(Decision {
+                outcome: AtomicUsize::new(CAS_PENDING),
+                attempts: AtomicUsize::new(CAS_FAILURE + 1),
+            }),
+        }
+    }
+    pub fn expected(&self) -> A::Value {
+        self.expected
+    }
+    pub fn new_value(&self) -> A::Value {
+        self.new
+    }
+    fn outcome(&self) -> Option<Result<(), ()>> {
+        match self.decision.outcome.load(Ordering::SeqCst) {
+            CAS_PENDING => None,
+            CAS_FAILURE => Some(Err(())),
+            _ => Some(Ok(())),
+        }
+    }
+}
+impl<A: CasTarget> Clone for CasDesc<A> {
+    fn clone(&self) -> Self {
+        CasDesc {
+            target: self.target,
+            expected: self.expected,
+            new: self.new,
+            decision: Arc::clone(&self.decision),
+        }
+    }
+}
+impl<A: CasTarget> CasDescriptor for CasDesc<A> {
+    fn state(&self) -> CasState {
+        match self.outcome() {
+            None => CasState::Pending,
+            Some(Ok(())) => CasState::Success,
+            Some(Err(())) => CasState::Failure,
+        }
+    }
+    fn execute(&self) -> Result<(), ()> {
+        let target = unsafe { &*self.target }.word();
+        let expected = A::to_word(self.expected);
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        loop {
+            if let Some(outcome) = self.outcome() {
+                return outcome;
+            }
+            let attempt = Box::into_raw(Box::new(CasAttempt {
+                decision: Arc::clone(&self.decision),
+                number: self.decision.attempts.fetch_add(1, Ordering::Relaxed),
+                expected,
+                new: A::to_word(self.new),
+            }));
+            let w = A::attempt_word(attempt);
+            match target.compare_exchange(expected, w, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => {
+                    // protect our attempt before finishing it, another helper may finish and
+                    // retire it first, then the target no longer holds w and we are done.
+                    let (now, cur) = unsafe {
+                        holder.load_word(target, |now| {
+                            if now == w { attempt } else { ptr::null_mut() }
+                        })
+                    };
+                    if let Some(cur) = cur {
+                        cur.finish(target, now);
+                    }
+                }
+                Err(cur) => {
+                    drop(unsafe { Box::from_raw(attempt) });
+                    if A::word_attempt(cur).is_some() {
+                        // someone's attempt is in the way, maybe ours, finish it and retry.
+                        let _ = A::load(unsafe { &*self.target });
+                    } else {
+                        // target is not expected, fails unless another helper decided first.
+                        let _ = self.decision.outcome.compare_exchange(
+                            CAS_PENDING,
+                            CAS_FAILURE,
+                            Ordering::SeqCst,
+                            Ordering::SeqCst,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+// a list of CasDescs on one kind of target.
+pub struct CasDescs<A: CasTarget>(pub Vec<CasDesc<A>>);
+impl<A: CasTarget> Clone for CasDescs<A> {
+    fn clone(&self) -> Self {
+        CasDescs(self.0.clone())
+    }
+}
+impl<A: CasTarget> Index<usize> for CasDescs<A> {
+    type Output = CasDesc<A>;
+    fn index(&self, i: usize) -> &CasDesc<A> {
+        &self.0[i]
+    }
+}
+impl<A: CasTarget> CasDescriptors<CasDesc<A>> for CasDescs<A> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
 }
 
 // A normalized lockfree generates CASes from Input Ops to executor run. 
@@ -28,7 +328,9 @@ pub trait NormalizedLockFree {
     type Cas: CasDescriptor;
     type Cases: CasDescriptors<Self::Cas> + Clone;
     
-    fn generate(&self, op: &Self::Input, contention: &mut ContentionMeasure) -> Self::Cases;
+    // Err when contention.detected() said so, the op then moves to the slow path.
+    fn generate(&self, op: &Self::Input, contention: &mut ContentionMeasure)
+                -> Result<Self::Cases, Contention>;
     fn wrap_up(&self, executed: Result<(), usize>, performed: &Self::Cases, 
                 //contention: &mut ContentionMeasure
             ) 
@@ -42,30 +344,476 @@ enum OperationState<LF: NormalizedLockFree> {
     PostCas(LF::Cases, Result<(),usize>),
     Completed(LF::Output),
 }
+impl<LF: NormalizedLockFree> OperationState<LF> {
+    fn kind(&self) -> StateKind {
+        match self {
+            OperationState::PreCas => StateKind::PreCas,
+            OperationState::ExecuteCas(_) => StateKind::ExecuteCas,
+            OperationState::PostCas(..) => StateKind::PostCas,
+            OperationState::Completed(_) => StateKind::Completed,
+        }
+    }
+}
+// the owner and the help queue each hold a ref, the last to let go retires the box.
+// Helpers only touch it protected by a hazptr, from peek.
+// val is swapped to a new record on every state change, the superseded record is retired
+// by the helper that swapped it out. Readers protect val before they deref it.
 struct OperationRecordBox<LF: NormalizedLockFree> {
     val: AtomicPtr<OperationRecord<LF>>,
+    refs: AtomicUsize,
+}
+impl<LF: NormalizedLockFree> OperationRecordBox<LF> {
+    fn new(record: OperationRecord<LF>) -> *const Self {
+        Box::into_raw(Box::new(OperationRecordBox {
+            val: AtomicPtr::new(Box::into_raw(Box::new(record))),
+            refs: AtomicUsize::new(2),
+        }))
+    }
+    // Safety: the owner and the queue each release once.
+    unsafe fn release(orb: *const Self) {
+        if unsafe { &*orb }.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            unsafe { retire(orb as *mut Self) };
+        }
+    }
+}
+// the orb is unreachable, so is its current record, no helper protects either.
+impl<LF: NormalizedLockFree> Drop for OperationRecordBox<LF> {
+    fn drop(&mut self) {
+        let record = self.val.with_mut(|record| *record);
+        if !record.is_null() {
+            drop(unsafe { Box::from_raw(record) });
+        }
+    }
 }
 struct OperationRecord<LF: NormalizedLockFree> {
-    owner: std::thread::ThreadId,
+    owner: thread::ThreadId,
     // each state enum encapsulates the arg/result  
     input: LF::Input,
     state: OperationState<LF>,
 }
 
-pub struct HelpQueue<LF> {
-    _o: PhantomData<LF>,
+// Help queue nodes, op descriptors, OperationRecordBoxes and superseded OperationRecords are
+// retired into their own domain.
+pub struct HelpQueueFamily;
+const_static! {
+    static HELP_DOMAIN: HazPtrDomain<HelpQueueFamily> = HazPtrDomain::new(&HelpQueueFamily);
+}
+
+impl<'d, LF: NormalizedLockFree + 'd> HazPtrObject<'d, HelpQueueFamily> for OperationRecordBox<LF> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+impl<'d, LF: NormalizedLockFree + 'd> HazPtrObject<'d, HelpQueueFamily> for OperationRecord<LF> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+impl<'d, LF: NormalizedLockFree + 'd> HazPtrObject<'d, HelpQueueFamily> for QNode<LF> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+impl<'d, LF: NormalizedLockFree + 'd> HazPtrObject<'d, HelpQueueFamily> for OpDesc<LF> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+// Safety: ptr is from Box::into_raw, no longer reachable by new readers, and retired once.
+unsafe fn retire<'d, T: HazPtrObject<'d, HelpQueueFamily>>(ptr: *mut T) {
+    unsafe { { &mut *ptr }.retire(deleters::_drop_box) };
+}
+
+// Each thread owns a slot in the help queue state array. Slots are handed out on first use
+// and taken back at thread exit, so any number of threads can come and go, at most
+// MAX_THREADS of them at once. A thread that finds every slot taken asks again next time.
+// std atomics even under loom: the main thread of a model hands its slot back only after
+// the execution ended, every thread has the same slot in every execution all the same.
+pub const MAX_THREADS: usize = 128;
+static THREAD_SLOTS: [std::sync::atomic::AtomicBool; MAX_THREADS] =
+    [const { std::sync::atomic::AtomicBool::new(false) }; MAX_THREADS];
+// helpers scan state up to the highest slot ever handed out.
+const_static! {
+    static THREADS_HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
+}
+struct ThreadIndex(usize);
+impl ThreadIndex {
+    fn claim() -> Option<Self> {
+        for (i, slot) in THREAD_SLOTS.iter().enumerate() {
+            if !slot.swap(true, Ordering::AcqRel) {
+                THREADS_HIGH_WATER.fetch_max(i + 1, Ordering::SeqCst);
+                return Some(ThreadIndex(i));
+            }
+        }
+        None
+    }
+}
+impl Drop for ThreadIndex {
+    fn drop(&mut self) {
+        THREAD_SLOTS[self.0].store(false, Ordering::Release);
+    }
+}
+thread_local! {
+    static THREAD_INDEX: std::cell::OnceCell<ThreadIndex> = const { std::cell::OnceCell::new() };
+}
+// None while MAX_THREADS other threads hold a slot.
+fn thread_index() -> Option<usize> {
+    THREAD_INDEX.with(|index| {
+        if index.get().is_none() {
+            let _ = index.set(ThreadIndex::claim()?);
+        }
+        index.get().map(|index| index.0)
+    })
+}
+
+// Kogan-Petrank wait-free queue, a Michael-Scott queue where every op first publishes an
+// OpDesc in state[tid] with a phase, then helps every pending op with a phase <= its own,
+// so an op is done after a bounded number of steps whatever the other threads do.
+// Dequeue is conditional, Timnat-Petrank: remove the front only if it is the given orb.
+//
+// head -> sentinel -> node(orb) -> node(orb) <- tail
+//
+// Nodes, descs and orbs are retired into HELP_DOMAIN. Every deref is protected by a hazptr
+// in Guards, and a node reached by next is only used after head or tail is seen unchanged.
+const NO_THREAD: usize = usize::MAX;
+struct QNode<LF: NormalizedLockFree> {
+    value: AtomicPtr<OperationRecordBox<LF>>, // null in the first sentinel, never changes.
+    next: AtomicPtr<QNode<LF>>,
+    enq_tid: usize,
+    deq_tid: AtomicUsize, // the dequeue that claimed the node as its sentinel.
+}
+impl<LF: NormalizedLockFree> QNode<LF> {
+    fn new(value: *const OperationRecordBox<LF>, enq_tid: usize) -> *mut Self {
+        Box::into_raw(Box::new(QNode {
+            value: AtomicPtr::new(value as *mut _),
+            next: AtomicPtr::new(ptr::null_mut()),
+            enq_tid,
+            deq_tid: AtomicUsize::new(NO_THREAD),
+        }))
+    }
+}
+// immutable, a state change swaps in a new desc and retires the old one.
+struct OpDesc<LF: NormalizedLockFree> {
+    phase: u64,
+    pending: bool,
+    enqueue: bool,
+    // enqueue: the node to link. dequeue: the sentinel it claimed, null when it failed.
+    node: *mut QNode<LF>,
+    // dequeue only if the node after the sentinel holds this orb.
+    front: *const OperationRecordBox<LF>,
+}
+impl<LF: NormalizedLockFree> OpDesc<LF> {
+    fn with(&self, pending: bool, node: *mut QNode<LF>) -> *mut Self {
+        Box::into_raw(Box::new(OpDesc {
+            phase: self.phase,
+            pending,
+            enqueue: self.enqueue,
+            node,
+            front: self.front,
+        }))
+    }
+}
+
+// hazptr slots of one queue op. Helpers re-protect what they need, so after calling
+// another helper a caller must re-load before it derefs again.
+type Guards = HazPtrArray<'static, HelpQueueFamily, 4>;
+const FIRST: usize = 0;
+const LAST: usize = 1;
+const NEXT: usize = 2;
+const DESC: usize = 3;
+// protect the ptr behind atomic_ptr in slot, null when empty.
+fn protect<'o, T>(guards: &Guards, slot: usize, atomic_ptr: &AtomicPtr<T>) -> *mut T
+where
+    T: HazPtrObject<'o, HelpQueueFamily>,
+{
+    // Safety: the queue only publishes Box::into_raw ptrs, and retires them into HELP_DOMAIN.
+    unsafe { guards.load(slot, atomic_ptr) }.map_or(ptr::null_mut(), |t| t as *const T as *mut T)
+}
+
+pub struct HelpQueue<LF: NormalizedLockFree> {
+    head: AtomicPtr<QNode<LF>>,
+    tail: AtomicPtr<QNode<LF>>,
+    state: Box<[AtomicPtr<OpDesc<LF>>]>, // null, or the latest op of thread index i.
+    phase: AtomicU64,
+    // ops thread index i ran since it last helped, only touched by that thread.
+    ops_since_help: Box<[AtomicUsize]>,
 }
 impl<LF: NormalizedLockFree> HelpQueue<LF> {
+    pub fn new() -> Self {
+        let sentinel = QNode::new(ptr::null(), NO_THREAD);
+        HelpQueue {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+            state: (0..MAX_THREADS).map(|_| AtomicPtr::new(ptr::null_mut())).collect(),
+            phase: AtomicU64::new(0),
+            ops_since_help: (0..MAX_THREADS).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+    // true on every k-th op of this thread. A thread without a slot keeps no count, it helps
+    // every time.
+    fn help_due(&self, k: usize) -> bool {
+        let Some(tid) = thread_index() else {
+            return true;
+        };
+        let ops = &self.ops_since_help[tid];
+        let n = ops.load(Ordering::Relaxed) + 1;
+        ops.store(if n >= k { 0 } else { n }, Ordering::Relaxed);
+        n >= k
+    }
+    fn guards() -> Guards {
+        HazPtrArray::for_domain(&HELP_DOMAIN)
+    }
+
     fn enqueue(&self, help: *const OperationRecordBox<LF>) {
-        let _ = help;
+        let tid = thread_index().expect("only threads holding a slot enqueue");
+        let guards = Self::guards();
+        let node = QNode::new(help, tid);
+        self.publish(tid, true, node, ptr::null(), &guards);
+        self.help_finish_enq(&guards);
     }
-    fn peek(&self) -> Option<*const OperationRecordBox<LF>> {
-        None
+    // the front orb, protected by holder until it is re-used. The orb stays valid, but may be
+    // dequeued meanwhile.
+    fn peek(
+        &self,
+        holder: &mut HazPtrHolder<'static, HelpQueueFamily>,
+    ) -> Option<*const OperationRecordBox<LF>> {
+        let guards = Self::guards();
+        loop {
+            let first = protect(&guards, FIRST, &self.head);
+            let next = protect(&guards, NEXT, &unsafe { &*first }.next);
+            if first != self.head.load(Ordering::SeqCst) {
+                continue;
+            }
+            // first is still head, so next is not dequeued yet.
+            let next = unsafe { next.as_ref() }?;
+            // Safety: orbs are retired after their node is dequeued, see below.
+            let orb = unsafe { holder.load(&next.value) }.map(|orb| orb as *const _);
+            // still the front after we protected it, the queue still holds its ref.
+            if first == self.head.load(Ordering::SeqCst) {
+                return orb;
+            }
+        }
     }
     fn try_remove_front(&self, front: *const OperationRecordBox<LF>) -> Result<(), ()> {
-        let _ = front;
+        // leave it to a thread with a slot, the owner's op is complete all the same.
+        let Some(tid) = thread_index() else {
+            return Err(());
+        };
+        let guards = Self::guards();
+        self.publish(tid, false, ptr::null_mut(), front, &guards);
+        self.help_finish_deq(&guards);
+        // ours and done. Helpers may still swap in an equal desc, so protect it.
+        let desc = protect(&guards, DESC, &self.state[tid]);
+        if unsafe { &*desc }.node.is_null() {
+            return Err(());
+        }
+        // exactly one dequeue claims the node of front, it lets go the queue's ref.
+        unsafe { OperationRecordBox::release(front) };
         Ok(())
     }
+
+    // announce our op in state[tid], with a phase newer than every op announced before it,
+    // and help all ops up to our phase, ours included.
+    fn publish(
+        &self,
+        tid: usize,
+        enqueue: bool,
+        node: *mut QNode<LF>,
+        front: *const OperationRecordBox<LF>,
+        guards: &Guards,
+    ) {
+        let phase = self.phase.fetch_add(1, Ordering::SeqCst) + 1;
+        let desc = Box::into_raw(Box::new(OpDesc { phase, pending: true, enqueue, node, front }));
+        // only we replace a desc that is not pending, no cas needed.
+        let old = self.state[tid].swap(desc, Ordering::SeqCst);
+        if !old.is_null() {
+            unsafe { retire(old) };
+        }
+        self.help(phase, guards);
+    }
+    // desc of tid if it still has an op pending at or before phase, protected in DESC.
+    fn pending_desc(&self, tid: usize, phase: u64, guards: &Guards) -> Option<*mut OpDesc<LF>> {
+        let desc = protect(guards, DESC, &self.state[tid]);
+        let d = unsafe { desc.as_ref() }?;
+        (d.pending && d.phase <= phase).then_some(desc)
+    }
+    // replace cur desc of tid, retire it when we won.
+    fn swap_desc(&self, tid: usize, cur: *mut OpDesc<LF>, new: *mut OpDesc<LF>) -> bool {
+        match self.state[tid].compare_exchange(cur, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => {
+                unsafe { retire(cur) };
+                true
+            }
+            Err(_) => {
+                drop(unsafe { Box::from_raw(new) });
+                false
+            }
+        }
+    }
+
+    fn help(&self, phase: u64, guards: &Guards) {
+        let threads = THREADS_HIGH_WATER.load(Ordering::SeqCst);
+        for tid in 0..threads {
+            if let Some(desc) = self.pending_desc(tid, phase, guards) {
+                if unsafe { &*desc }.enqueue {
+                    self.help_enq(tid, phase, guards);
+                } else {
+                    self.help_deq(tid, phase, guards);
+                }
+            }
+        }
+    }
+
+    fn help_enq(&self, tid: usize, phase: u64, guards: &Guards) {
+        while self.pending_desc(tid, phase, guards).is_some() {
+            let last = protect(guards, LAST, &self.tail);
+            let next = unsafe { &*last }.next.load(Ordering::SeqCst);
+            if last != self.tail.load(Ordering::SeqCst) {
+                continue;
+            }
+            if !next.is_null() {
+                // tail lags, finish the enqueue that linked next first.
+                self.help_finish_enq(guards);
+                continue;
+            }
+            // re-check right before linking: once tail passed the node, its desc is done.
+            if let Some(desc) = self.pending_desc(tid, phase, guards) {
+                let node = unsafe { &*desc }.node;
+                if unsafe { &*last }
+                    .next
+                    .compare_exchange(ptr::null_mut(), node, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    self.help_finish_enq(guards);
+                    return;
+                }
+            }
+        }
+    }
+    // mark the enqueue of the node after tail done, then swing tail to it.
+    fn help_finish_enq(&self, guards: &Guards) {
+        let last = protect(guards, LAST, &self.tail);
+        let next = protect(guards, NEXT, &unsafe { &*last }.next);
+        // tail did not move, so next is not dequeued, head never passes tail.
+        if next.is_null() || last != self.tail.load(Ordering::SeqCst) {
+            return;
+        }
+        let tid = unsafe { &*next }.enq_tid;
+        let cur = protect(guards, DESC, &self.state[tid]);
+        if let Some(desc) = unsafe { cur.as_ref() } {
+            if desc.pending && desc.node == next && last == self.tail.load(Ordering::SeqCst) {
+                self.swap_desc(tid, cur, desc.with(false, next));
+            }
+        }
+        let _ = self.tail.compare_exchange(last, next, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    fn help_deq(&self, tid: usize, phase: u64, guards: &Guards) {
+        while self.pending_desc(tid, phase, guards).is_some() {
+            let first = protect(guards, FIRST, &self.head);
+            let last = protect(guards, LAST, &self.tail);
+            let next = protect(guards, NEXT, &unsafe { &*first }.next);
+            if first != self.head.load(Ordering::SeqCst) {
+                continue;
+            }
+            if first == last {
+                if !next.is_null() {
+                    // tail lags behind a linked node, never let head pass tail.
+                    self.help_finish_enq(guards);
+                    continue;
+                }
+                // empty, the op fails.
+                if let Some(cur) = self.pending_desc(tid, phase, guards) {
+                    if last == self.tail.load(Ordering::SeqCst) {
+                        let desc = unsafe { &*cur };
+                        self.swap_desc(tid, cur, desc.with(false, ptr::null_mut()));
+                    }
+                }
+                continue;
+            }
+            let Some(cur) = self.pending_desc(tid, phase, guards) else {
+                break;
+            };
+            let desc = unsafe { &*cur };
+            // front is not ours, the op fails. A claimed sentinel always has the right front,
+            // its next never changes, so this can not undo a claim.
+            if !ptr::eq(unsafe { &*next }.value.load(Ordering::SeqCst), desc.front) {
+                if first == self.head.load(Ordering::SeqCst) {
+                    self.swap_desc(tid, cur, desc.with(false, ptr::null_mut()));
+                }
+                continue;
+            }
+            if first == self.head.load(Ordering::SeqCst)
+                && desc.node != first
+                && !self.swap_desc(tid, cur, desc.with(true, first))
+            {
+                continue;
+            }
+            // claim the sentinel for tid, unless another dequeue got it first.
+            let _ = unsafe { &*first }.deq_tid.compare_exchange(
+                NO_THREAD,
+                tid,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+            self.help_finish_deq(guards);
+        }
+    }
+    // mark the dequeue that claimed head done, then swing head past it.
+    fn help_finish_deq(&self, guards: &Guards) {
+        let first = protect(guards, FIRST, &self.head);
+        let next = protect(guards, NEXT, &unsafe { &*first }.next);
+        let tid = unsafe { &*first }.deq_tid.load(Ordering::SeqCst);
+        if tid == NO_THREAD {
+            return;
+        }
+        let cur = protect(guards, DESC, &self.state[tid]);
+        if first == self.head.load(Ordering::SeqCst) && !next.is_null() {
+            if let Some(desc) = unsafe { cur.as_ref() } {
+                // a desc of a later op of tid has another node.
+                if desc.pending && desc.node == first {
+                    self.swap_desc(tid, cur, desc.with(false, first));
+                }
+            }
+            if self
+                .head
+                .compare_exchange(first, next, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                // the old sentinel, its orb went with the dequeue before it.
+                unsafe { retire(first) };
+            }
+        }
+    }
+}
+impl<LF: NormalizedLockFree> Default for HelpQueue<LF> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// &mut self, no op is in flight. Retire rather than free, a stale hazptr may still point here.
+impl<LF: NormalizedLockFree> Drop for HelpQueue<LF> {
+    fn drop(&mut self) {
+        let mut node = self.head.with_mut(|head| *head);
+        let mut sentinel = true;
+        while !node.is_null() {
+            let next = unsafe { &*node }.next.load(Ordering::SeqCst);
+            // the orbs still queued lose the queue's ref, the sentinel's went with its dequeue.
+            let orb = unsafe { &*node }.value.load(Ordering::SeqCst);
+            if !sentinel && !orb.is_null() {
+                unsafe { OperationRecordBox::release(orb) };
+            }
+            unsafe { retire(node) };
+            sentinel = false;
+            node = next;
+        }
+        for desc in self.state.iter_mut() {
+            let desc = desc.with_mut(|desc| *desc);
+            if !desc.is_null() {
+                unsafe { retire(desc) };
+            }
+        }
+    }
 }
 
 // WF executor take a normalizedLF which generate CAS ops.
@@ -74,8 +822,159 @@ impl<LF: NormalizedLockFree> HelpQueue<LF> {
 pub struct WaitFreeSimulator<LF: NormalizedLockFree> {
     algorithm: LF,
     help: HelpQueue<LF>,
+    contention_threshold: usize,
+    help_every: usize,
+    fast_ops: AtomicUsize,
+    slow_ops: AtomicUsize,
+    events: Option<EventLog>,
+}
+// ops completed on each path so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PathStats {
+    pub fast: usize,
+    pub slow: usize,
+}
+impl PathStats {
+    pub fn slow_ratio(&self) -> f64 {
+        self.slow as f64 / (self.fast + self.slow).max(1) as f64
+    }
+}
+
+// an OperationState without its data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateKind {
+    PreCas,
+    ExecuteCas,
+    PostCas,
+    Completed,
+}
+// the slow path op of owner went from one state to the next, by the helper whose record
+// swap won. from is None when the owner enqueued the op, cas_failed the index cas_execute
+// failed at on the way to PostCas. An owner runs one slow path op at a time.
+// seq orders the events of an op, events of ops running side by side only roughly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpEvent {
+    pub seq: usize,
+    pub owner: thread::ThreadId,
+    pub helper: thread::ThreadId,
+    pub from: Option<StateKind>,
+    pub to: StateKind,
+    pub cas_failed: Option<usize>,
+}
+
+// push-only list of OpEvents, newest first. Nodes are freed with the log only, readers
+// walk it unprotected.
+struct EventLog {
+    seq: AtomicUsize,
+    head: AtomicPtr<EventNode>,
+}
+struct EventNode {
+    event: OpEvent,
+    next: *mut EventNode,
+}
+impl EventLog {
+    fn new() -> Self {
+        EventLog {
+            seq: AtomicUsize::new(0),
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+    // take it before the record swap, the op's next swap reads our record and so comes
+    // after, with a larger seq. A lost swap leaves a gap.
+    fn next_seq(&self) -> usize {
+        self.seq.fetch_add(1, Ordering::Relaxed)
+    }
+    fn push(&self, event: OpEvent) {
+        let node = Box::into_raw(Box::new(EventNode { event, next: ptr::null_mut() }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // not shared until the cas publishes it.
+            unsafe { (*node).next = head };
+            match self.head.compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(now) => head = now,
+            }
+        }
+    }
+    fn events(&self) -> Vec<OpEvent> {
+        let mut events = Vec::new();
+        let mut node = self.head.load(Ordering::Acquire);
+        while !node.is_null() {
+            let n = unsafe { &*node };
+            events.push(n.event.clone());
+            node = n.next;
+        }
+        events.sort_by_key(|event| event.seq);
+        events
+    }
+}
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        let mut node = self.head.with_mut(|head| *head);
+        while !node.is_null() {
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next;
+        }
+    }
 }
 impl<LF: NormalizedLockFree> WaitFreeSimulator<LF> {
+    pub fn new(algorithm: LF) -> Self {
+        WaitFreeSimulator {
+            algorithm,
+            help: HelpQueue::new(),
+            contention_threshold: RETRY_THRESHOLD,
+            help_every: HELP_EVERY,
+            fast_ops: AtomicUsize::new(0),
+            slow_ops: AtomicUsize::new(0),
+            events: None,
+        }
+    }
+    // contention a fast path op tolerates, 0 sends an op to the slow path on its first retry.
+    pub fn contention_threshold(mut self, threshold: usize) -> Self {
+        self.contention_threshold = threshold;
+        self
+    }
+    // each thread helps once every k of its ops on this simulator. A larger k leaves more time
+    // to the fast path, a slow path op then waits for up to k ops of every other thread to get
+    // help.
+    pub fn help_every(mut self, k: usize) -> Self {
+        assert!(k > 0, "help_every needs k > 0");
+        self.help_every = k;
+        self
+    }
+    // log every state change of a slow path op, to find where a hung op is stuck and who
+    // helps it. Costs a fetch_add and an allocation per state change.
+    pub fn event_log(mut self) -> Self {
+        self.events = Some(EventLog::new());
+        self
+    }
+    // logged so far, by seq. Empty without event_log.
+    pub fn events(&self) -> Vec<OpEvent> {
+        self.events.as_ref().map_or_else(Vec::new, EventLog::events)
+    }
+    // events() one per line, e.g.
+    //     7 ThreadId(3) by ThreadId(5): ExecuteCas -> PostCas, cas 0 failed
+    pub fn timeline(&self) -> String {
+        let mut timeline = String::new();
+        for event in self.events() {
+            let from = event.from.map_or("enqueued".to_string(), |from| format!("{from:?}"));
+            timeline += &format!(
+                "{:6} {:?} by {:?}: {} -> {:?}",
+                event.seq, event.owner, event.helper, from, event.to
+            );
+            if let Some(i) = event.cas_failed {
+                timeline += &format!(", cas {i} failed");
+            }
+            timeline.push('\n');
+        }
+        timeline
+    }
+    pub fn path_stats(&self) -> PathStats {
+        PathStats {
+            fast: self.fast_ops.load(Ordering::Relaxed),
+            slow: self.slow_ops.load(Ordering::Relaxed),
+        }
+    }
     pub fn cas_execute(&self, descriptors: &LF::Cases) -> Result<(), usize> {
         let len = descriptors.len();
         for i in 0..len {
@@ -86,23 +985,32 @@ impl<LF: NormalizedLockFree> WaitFreeSimulator<LF> {
         Ok(())
     }
     pub fn help_first(&self) {
-        if let Some(help) = self.help.peek() {
+        // the orb stays protected while we help it.
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        if let Some(help) = self.help.peek(&mut holder) {
             self.help_op(unsafe {&*help} );
         }
     }
     fn help_op(&self, orb: &OperationRecordBox<LF>) {
+        // another helper may swap out and retire the record we read, keep it protected.
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
         loop {
-            let or = unsafe { &mut *orb.val.load(Ordering::SeqCst) };
+            // Safety: records are Box::into_raw ptrs retired into HELP_DOMAIN, val never null.
+            let or = unsafe { holder.load(&orb.val) }.expect("orb always has a record");
             let updated_or = match &or.state {
-                OperationState::Completed(result) => { 
-                    let _ = self.help.try_remove_front(orb); 
+                OperationState::Completed(_) => {
+                    // fails when another helper already took it off the front.
+                    let _ = self.help.try_remove_front(orb);
                     return;
                 }
                 OperationState::PreCas => {
-                    let mut contention = ContentionMeasure(0);
-                    let cas_list = self.algorithm.generate(&or.input, &mut contention);
+                    let mut contention = ContentionMeasure::unbounded();
+                    // the algorithm gave up on its own, read the record again and retry.
+                    let Ok(cas_list) = self.algorithm.generate(&or.input, &mut contention) else {
+                        continue;
+                    };
                     Box::new(OperationRecord {
-                        owner: or.owner.clone(),
+                        owner: or.owner,
                         input: or.input.clone(),
                         state: OperationState::ExecuteCas(cas_list),
                     })
@@ -110,91 +1018,1417 @@ impl<LF: NormalizedLockFree> WaitFreeSimulator<LF> {
                 OperationState::ExecuteCas(cas_list) => {
                     let outcome = self.cas_execute(cas_list);
                     Box::new(OperationRecord {
-                        owner: or.owner.clone(),
+                        owner: or.owner,
                         input: or.input.clone(),
                         state: OperationState::PostCas(cas_list.clone(), outcome),
                     })
                 }
                 OperationState::PostCas(cas_list, outcome) => {
-                    let contention = ContentionMeasure(0);
                     if let Ok(result) = self.algorithm.wrap_up(*outcome, cas_list) {
                         Box::new(OperationRecord{
-                                owner: or.owner.clone(),
+                                owner: or.owner,
                                 input: or.input.clone(),
                                 state: OperationState::Completed(result),
                         })
                     } else {
                         Box::new(OperationRecord{
-                            owner: or.owner.clone(),
+                            owner: or.owner,
                             input: or.input.clone(),
                             state: OperationState::PreCas,
                         })
                     }
                 }
             };
+            let event = self.events.as_ref().map(|log| OpEvent {
+                seq: log.next_seq(),
+                owner: or.owner,
+                helper: thread::current().id(),
+                from: Some(or.state.kind()),
+                to: updated_or.state.kind(),
+                cas_failed: match updated_or.state {
+                    OperationState::PostCas(_, Err(i)) => Some(i),
+                    _ => None,
+                },
+            });
             let updated_or = Box::into_raw(updated_or);
-            if orb.val.compare_exchange_weak(or as *mut OperationRecord<_>,
-                                             updated_or as *mut OperationRecord<_>,
-                                             Ordering::SeqCst, Ordering::SeqCst).is_err() {
-                let _ = unsafe { Box::from_raw(updated_or) };
+            match orb.val.compare_exchange_weak(or as *const OperationRecord<_> as *mut _,
+                                             updated_or,
+                                             Ordering::SeqCst, Ordering::SeqCst) {
+                // we swapped it out, we retire it. Other helpers may still be reading it.
+                Ok(old) => {
+                    if let (Some(log), Some(event)) = (&self.events, event) {
+                        log.push(event);
+                    }
+                    unsafe { retire(old) }
+                }
+                Err(_) => drop(unsafe { Box::from_raw(updated_or) }),
             }
         }
     }
     // WF wraps LF and provides run() that takes Input and ret Output, with cas in between.
+    // Wait-free for up to MAX_THREADS threads at once, each holds a help queue slot until it
+    // exits. A thread that finds every slot taken still completes its ops, lock-free only.
     pub fn run(&self, op: LF::Input) -> LF::Output {
-        let help = true;
-        if help {
+        if self.help.help_due(self.help_every) {
             self.help_first();
         }
-        // fast path
-        for retry in 0.. {
-            let mut contention = ContentionMeasure(0);
-            let cases = self.algorithm.generate(&op, &mut contention);
+        // fast path, until the op runs into more contention than the threshold.
+        let mut contention = ContentionMeasure::new(self.contention_threshold);
+        while let Ok(cases) = self.algorithm.generate(&op, &mut contention) {
             let result = self.cas_execute(&cases);
-            match self.algorithm.wrap_up(result, &cases) {
-                Ok(outcome) => { return outcome; },
-                Err(_) => {}
+            if let Ok(outcome) = self.algorithm.wrap_up(result, &cases) {
+                self.fast_ops.fetch_add(1, Ordering::Relaxed);
+                return outcome;
             }
-            if retry > RETRY_THRESHOLD {
+            if contention.detected().is_err() {
                 break;
             }
         }
-        // slow path, create a OpRecord and enqueu, then help_first
-        let orb = OperationRecordBox {
-            val: AtomicPtr::new(Box::into_raw(Box::new(OperationRecord {
-                owner: thread::current().id(),
-                input: op, // consume,
-                state: OperationState::PreCas,
-            }))),
-        };
-        self.help.enqueue(&orb);
-        loop {
-            let or = unsafe { &* orb.val.load(Ordering::SeqCst) };
+        self.slow_ops.fetch_add(1, Ordering::Relaxed);
+        self.run_slow(op)
+    }
+    // slow path, create a OpRecord and enqueu, then help_first
+    fn run_slow(&self, op: LF::Input) -> LF::Output {
+        if thread_index().is_none() {
+            // no slot to publish in, nobody would help us: retry the fast path for as long
+            // as it takes.
+            let mut contention = ContentionMeasure::unbounded();
+            loop {
+                let Ok(cases) = self.algorithm.generate(&op, &mut contention) else {
+                    continue;
+                };
+                let result = self.cas_execute(&cases);
+                if let Ok(outcome) = self.algorithm.wrap_up(result, &cases) {
+                    return outcome;
+                }
+            }
+        }
+        // the orb is on the heap, helpers that peeked it may outlive this call.
+        let orb = OperationRecordBox::new(OperationRecord {
+            owner: thread::current().id(),
+            input: op, // consume,
+            state: OperationState::PreCas,
+        });
+        if let Some(log) = &self.events {
+            let me = thread::current().id();
+            log.push(OpEvent {
+                seq: log.next_seq(),
+                owner: me,
+                helper: me,
+                from: None,
+                to: StateKind::PreCas,
+                cas_failed: None,
+            });
+        }
+        self.help.enqueue(orb);
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        let output = loop {
+            // we hold a ref, no need to protect our own orb, but helpers retire its records.
+            let or = unsafe { holder.load(&(*orb).val) }.expect("orb always has a record");
             if let OperationState::Completed(t) = &or.state {
                 break t.clone();
             } else {
                 self.help_first();
             }
-        }
+        };
+        unsafe { OperationRecordBox::release(orb) };
+        output
     }
 }
 
 
 //
 // client data struct impls NormalizedLockFree to generate struct specific CASes.
-struct LockFreeLinkedList<T> {
-    t: T,
+//
+// Harris sorted linked list set. A node is removed by marking its next ptr first, the mark
+// is the linearization point, then snipped out of the list by whoever walks by.
+// generate searches for the key, snipping marked nodes on the way, those cases are
+// parallelizable and done right away, and returns the one cas of the op:
+//   insert: pred.next from curr to a new node.
+//   remove: curr.next from succ to succ | MARK.
+// wrap_up retries on a failed cas, so the op linearizes at its successful one, or at the
+// search when the key is found, resp. missing.
+//
+// head -> node(1) -> node(3) -x-> node(4, marked) -> node(7) -> null
+//
+// Nodes of the list and the queue are retired into HELP_DOMAIN by whoever takes them out, so
+// walks protect every node they step on, see load_node. Cases of retired records may still
+// be executed by late helpers, see Pins.
+// load link, finishing an attempt in it first, and protect the node it points to, the mark
+// aside. The node is safe to deref only if the caller knows it was still reachable when
+// the link was seen unchanged after the protection: through a link of the structure itself,
+// or the unmarked next of a node that is protected the same way.
+fn load_node<'o, N>(holder: &mut HazPtrHolder<'static, HelpQueueFamily>, link: &AtomicPtr<N>) -> *mut N
+where
+    N: HazPtrObject<'o, HelpQueueFamily>,
+{
+    let node_of = |w| {
+        if AtomicPtr::<N>::word_attempt(w).is_some() {
+            ptr::null_mut()
+        } else {
+            without_mark(w as *mut N)
+        }
+    };
+    loop {
+        // Safety: nodes are Box::into_raw ptrs, retired into HELP_DOMAIN once taken out.
+        let (w, _) = unsafe { holder.load_word(link.word(), node_of) };
+        if AtomicPtr::<N>::word_attempt(w).is_none() {
+            return w as *mut N;
+        }
+        let _ = CasTarget::load(link);
+    }
 }
-impl<T> NormalizedLockFree for LockFreeLinkedList<T> {
+
+// Hazptrs on the nodes a case targets, or expects where a reused address would fool it.
+// Cases live on in records helpers still run after the op's walk moved on, the pins keep
+// those nodes from reclaim, and their addresses from reuse, until the last clone drops.
+struct Pins(Vec<HazPtrHolder<'static, HelpQueueFamily>>);
+impl Pins {
+    // Safety: each node is null or protected by the caller.
+    unsafe fn new<'o, N>(nodes: &[*mut N]) -> Arc<Self>
+    where
+        N: HazPtrObject<'o, HelpQueueFamily>,
+    {
+        let pins = nodes.iter().filter(|node| !node.is_null()).map(|&node| {
+            let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+            unsafe { holder.protect_ptr(node) };
+            holder
+        });
+        Arc::new(Pins(pins.collect()))
+    }
 }
 
-// struct WaitFreeLinkedList<T> {
-//     // all ops impled by WF simulator executor.
-//     simulator: WaitFreeSimulator<LockFreeLinkedList<T>>,
-// }
-// impl<T> WaitFreeLinkedList<T> {
-//     pub fn enqueue(&self, t: T) {
-//         self.simulator.run(t);
-//     }
-// }
+const MARK: usize = 2; // bit 0 of a next ptr is the CasTarget tag.
+fn marked<T>(p: *mut T) -> bool {
+    p as usize & MARK != 0
+}
+fn with_mark<T>(p: *mut T) -> *mut T {
+    (p as usize | MARK) as *mut T
+}
+fn without_mark<T>(p: *mut T) -> *mut T {
+    (p as usize & !MARK) as *mut T
+}
 
+pub struct Node<T> {
+    key: T,
+    next: AtomicPtr<Node<T>>,
+}
+impl<'d, T: 'd> HazPtrObject<'d, HelpQueueFamily> for Node<T> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+
+#[derive(Clone)]
+pub enum ListOp<T> {
+    Insert(T),
+    Remove(T),
+}
+
+pub struct LockFreeLinkedList<T> {
+    head: AtomicPtr<Node<T>>,
+    _owns: std::marker::PhantomData<Box<Node<T>>>,
+}
+impl<T> LockFreeLinkedList<T> {
+    pub fn new() -> Self {
+        LockFreeLinkedList {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _owns: std::marker::PhantomData,
+        }
+    }
+    // the next ptr of pred, head when pred is null.
+    fn link(&self, pred: *mut Node<T>) -> &AtomicPtr<Node<T>> {
+        match unsafe { pred.as_ref() } {
+            Some(pred) => &pred.next,
+            None => &self.head,
+        }
+    }
+}
+// hazptrs of a search, on pred, curr and succ.
+struct Walk {
+    pred: HazPtrHolder<'static, HelpQueueFamily>,
+    curr: HazPtrHolder<'static, HelpQueueFamily>,
+    succ: HazPtrHolder<'static, HelpQueueFamily>,
+}
+impl Walk {
+    fn new() -> Self {
+        Walk {
+            pred: HazPtrHolder::for_domain(&HELP_DOMAIN),
+            curr: HazPtrHolder::for_domain(&HELP_DOMAIN),
+            succ: HazPtrHolder::for_domain(&HELP_DOMAIN),
+        }
+    }
+    // a step along the list, pred <- curr <- succ.
+    fn step(&mut self) {
+        std::mem::swap(&mut self.pred, &mut self.curr);
+        std::mem::swap(&mut self.curr, &mut self.succ);
+    }
+}
+impl<T> Default for LockFreeLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: Ord> LockFreeLinkedList<T> {
+    // link(pred) is curr, the first unmarked node with a key >= key, or null. pred is null
+    // for the head. Both stay protected by walk until it is used again.
+    fn search(
+        &self,
+        key: &T,
+        walk: &mut Walk,
+        contention: &mut ContentionMeasure,
+    ) -> Result<(*mut Node<T>, *mut Node<T>), Contention> {
+        'retry: loop {
+            let mut pred = ptr::null_mut();
+            let mut curr = load_node(&mut walk.curr, &self.head);
+            loop {
+                let Some(node) = (unsafe { curr.as_ref() }) else {
+                    return Ok((pred, curr));
+                };
+                let succ = load_node(&mut walk.succ, &node.next);
+                if marked(succ) {
+                    // Safety: pred is protected by walk, later helpers never run a snip.
+                    let snip = unsafe { CasDesc::new(self.link(pred), curr, without_mark(succ)) };
+                    if snip.execute().is_err() {
+                        contention.detected()?;
+                        continue 'retry;
+                    }
+                    // exactly one snip can succeed on a node, ours took it out.
+                    unsafe { retire(curr) };
+                    // succ is snipped only after curr, it was still linked when we protected it.
+                    curr = without_mark(succ);
+                    std::mem::swap(&mut walk.curr, &mut walk.succ);
+                    continue;
+                }
+                if node.key >= *key {
+                    return Ok((pred, curr));
+                }
+                pred = curr;
+                curr = succ;
+                walk.step();
+            }
+        }
+    }
+    // a search, as a walk can not step past a removed node, whose next may be gone already.
+    // It snips them itself and starts over only on a snip lost to another op, lock-free.
+    pub fn contains(&self, key: &T) -> bool {
+        let mut walk = Walk::new();
+        let Ok((_, curr)) = self.search(key, &mut walk, &mut ContentionMeasure::unbounded()) else {
+            unreachable!("an unbounded search never gives up")
+        };
+        unsafe { curr.as_ref() }.is_some_and(|node| node.key == *key)
+    }
+}
+// nothing is in flight, free what is left on the list. Snipped nodes are HELP_DOMAIN's.
+impl<T> Drop for LockFreeLinkedList<T> {
+    fn drop(&mut self) {
+        let mut node = without_mark(self.head.with_mut(|head| *head));
+        while !node.is_null() {
+            let next = without_mark(unsafe { &mut *node }.next.with_mut(|next| *next));
+            drop(unsafe { Box::from_raw(node) });
+            node = next;
+        }
+    }
+}
+
+// the node an insert or enqueue generated. Helpers share it through clones of the cases,
+// the last one frees it unless its cas linked it in.
+struct FreshNode<N> {
+    node: *mut N,
+    link: CasDesc<AtomicPtr<N>>,
+}
+impl<N> Drop for FreshNode<N> {
+    fn drop(&mut self) {
+        if self.link.state() != CasState::Success {
+            drop(unsafe { Box::from_raw(self.node) });
+        }
+    }
+}
+// the one cas of an op, none when the search settled it: the key to insert is there, or
+// the key to remove is not.
+pub struct ListCases<T> {
+    cas: Option<CasDesc<AtomicPtr<Node<T>>>>,
+    fresh: Option<Arc<FreshNode<Node<T>>>>,
+    // insert: pred and curr. remove: curr, a new succ behind it is removed all the same.
+    pins: Option<Arc<Pins>>,
+}
+impl<T> Clone for ListCases<T> {
+    fn clone(&self) -> Self {
+        ListCases {
+            cas: self.cas.clone(),
+            fresh: self.fresh.clone(),
+            pins: self.pins.clone(),
+        }
+    }
+}
+impl<T> Index<usize> for ListCases<T> {
+    type Output = CasDesc<AtomicPtr<Node<T>>>;
+    fn index(&self, i: usize) -> &Self::Output {
+        assert_eq!(i, 0);
+        self.cas.as_ref().expect("no cas")
+    }
+}
+impl<T> CasDescriptors<CasDesc<AtomicPtr<Node<T>>>> for ListCases<T> {
+    fn len(&self) -> usize {
+        self.cas.is_some() as usize
+    }
+}
+
+impl<T: Ord + Clone> NormalizedLockFree for LockFreeLinkedList<T> {
+    type Input = ListOp<T>;
+    type Output = bool;
+    type Cas = CasDesc<AtomicPtr<Node<T>>>;
+    type Cases = ListCases<T>;
+
+    fn generate(
+        &self,
+        op: &ListOp<T>,
+        contention: &mut ContentionMeasure,
+    ) -> Result<ListCases<T>, Contention> {
+        let settled = || ListCases { cas: None, fresh: None, pins: None };
+        let mut walk = Walk::new();
+        match op {
+            ListOp::Insert(key) => {
+                let (pred, curr) = self.search(key, &mut walk, contention)?;
+                if unsafe { curr.as_ref() }.is_some_and(|node| node.key == *key) {
+                    return Ok(settled());
+                }
+                let node = Box::into_raw(Box::new(Node {
+                    key: key.clone(),
+                    next: AtomicPtr::new(curr),
+                }));
+                // Safety: pred is the list head or a node pinned by the cases.
+                let link = unsafe { CasDesc::new(self.link(pred), curr, node) };
+                Ok(ListCases {
+                    cas: Some(link.clone()),
+                    fresh: Some(Arc::new(FreshNode { node, link })),
+                    pins: Some(unsafe { Pins::new(&[pred, curr]) }),
+                })
+            }
+            ListOp::Remove(key) => loop {
+                let (_, curr) = self.search(key, &mut walk, contention)?;
+                let Some(node) = unsafe { curr.as_ref() }.filter(|node| node.key == *key) else {
+                    return Ok(settled());
+                };
+                let succ = CasTarget::load(&node.next);
+                // already removed by someone else, search again to snip it.
+                if marked(succ) {
+                    contention.detected()?;
+                    continue;
+                }
+                return Ok(ListCases {
+                    // Safety: node is pinned by the cases.
+                    cas: Some(unsafe { CasDesc::new(&node.next, succ, with_mark(succ)) }),
+                    fresh: None,
+                    pins: Some(unsafe { Pins::new(&[curr]) }),
+                });
+            },
+        }
+    }
+    // true when the cas added, resp. removed the key. Settled by the search answers false.
+    fn wrap_up(&self, executed: Result<(), usize>, performed: &ListCases<T>) -> Result<bool, Contention> {
+        if performed.cas.is_none() {
+            return Ok(false);
+        }
+        executed.map(|_| true).map_err(|_| Contention)
+    }
+}
+
+pub struct WaitFreeLinkedList<T: Ord + Clone> {
+    // all ops impled by WF simulator executor.
+    simulator: WaitFreeSimulator<LockFreeLinkedList<T>>,
+}
+impl<T: Ord + Clone> WaitFreeLinkedList<T> {
+    pub fn new() -> Self {
+        WaitFreeLinkedList {
+            simulator: WaitFreeSimulator::new(LockFreeLinkedList::new()),
+        }
+    }
+    // false when key was already in the set.
+    pub fn insert(&self, key: T) -> bool {
+        self.simulator.run(ListOp::Insert(key))
+    }
+    // false when key was not in the set.
+    pub fn remove(&self, key: T) -> bool {
+        self.simulator.run(ListOp::Remove(key))
+    }
+    pub fn contains(&self, key: &T) -> bool {
+        self.simulator.algorithm.contains(key)
+    }
+}
+impl<T: Ord + Clone> Default for WaitFreeLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Michael-Scott queue in normalized form, the second client of the simulator.
+//
+// head -> sentinel -> node(a) -> node(b) <- tail
+//
+// enqueue: generate finds the last node, swinging a lagging tail along, and returns the
+//   cas of last.next from null to a new node. wrap_up swings tail to the new node.
+// dequeue: generate returns the cas of head from the sentinel to its next, whose value is
+//   the result, or no cas when the queue is empty. wrap_up retires the old sentinel.
+// A failed cas retries. As in the list, a dequeued node goes to HELP_DOMAIN, pinned by the
+// cases still targeting it. Its value goes apart: it is boxed on its own and retired once
+// returned, a dequeue that generated late clones it under a hazptr.
+pub struct QueueNode<T> {
+    value: AtomicPtr<QueueValue<T>>, // null in the first sentinel, and once dequeued.
+    next: AtomicPtr<QueueNode<T>>,
+    dequeued: AtomicBool, // helpers all run wrap_up, only the first one retires.
+}
+impl<T> QueueNode<T> {
+    fn new(value: Option<T>) -> Box<Self> {
+        let value = value.map_or(ptr::null_mut(), |value| Box::into_raw(Box::new(QueueValue(value))));
+        Box::new(QueueNode {
+            value: AtomicPtr::new(value),
+            next: AtomicPtr::new(ptr::null_mut()),
+            dequeued: AtomicBool::new(false),
+        })
+    }
+}
+// a node freed with the queue, or never linked, still owns its value.
+impl<T> Drop for QueueNode<T> {
+    fn drop(&mut self) {
+        let value = self.value.with_mut(|value| *value);
+        if !value.is_null() {
+            drop(unsafe { Box::from_raw(value) });
+        }
+    }
+}
+struct QueueValue<T>(T);
+impl<'d, T: 'd> HazPtrObject<'d, HelpQueueFamily> for QueueValue<T> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+impl<'d, T: 'd> HazPtrObject<'d, HelpQueueFamily> for QueueNode<T> {
+    fn domain(&self) -> &'d HazPtrDomain<HelpQueueFamily> {
+        &HELP_DOMAIN
+    }
+}
+
+#[derive(Clone)]
+pub enum QueueOp<T> {
+    Enqueue(T),
+    Dequeue,
+}
+
+pub struct LockFreeQueue<T> {
+    head: AtomicPtr<QueueNode<T>>,
+    tail: AtomicPtr<QueueNode<T>>,
+    _owns: std::marker::PhantomData<Box<QueueNode<T>>>,
+}
+impl<T> LockFreeQueue<T> {
+    pub fn new() -> Self {
+        let sentinel = Box::into_raw(QueueNode::new(None));
+        LockFreeQueue {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+            _owns: std::marker::PhantomData,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        let first = load_node(&mut holder, &self.head);
+        CasTarget::load(&unsafe { &*first }.next).is_null()
+    }
+    // tail lags behind last, swing it to next. Parallelizable, done right away.
+    fn swing_tail(&self, last: *mut QueueNode<T>, next: *mut QueueNode<T>) {
+        // Safety: tail is our own field.
+        let _ = unsafe { CasDesc::new(&self.tail, last, next) }.execute();
+    }
+}
+impl<T> Default for LockFreeQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// nothing is in flight, free the sentinel with the nodes still queued. Dequeued ones are
+// HELP_DOMAIN's.
+impl<T> Drop for LockFreeQueue<T> {
+    fn drop(&mut self) {
+        let mut node = self.head.with_mut(|head| *head);
+        while !node.is_null() {
+            let next = unsafe { &mut *node }.next.with_mut(|next| *next);
+            drop(unsafe { Box::from_raw(node) });
+            node = next;
+        }
+    }
+}
+
+// the one cas of an op, none when a dequeue found the queue empty.
+pub struct QueueCases<T> {
+    cas: Option<CasDesc<AtomicPtr<QueueNode<T>>>>,
+    fresh: Option<Arc<FreshNode<QueueNode<T>>>>, // the node an enqueue links,
+    last: *mut QueueNode<T>,                     // after this one.
+    value: Option<T>,                            // what a dequeue returns.
+    pins: Option<Arc<Pins>>,                     // last, resp. the sentinel.
+}
+impl<T: Clone> Clone for QueueCases<T> {
+    fn clone(&self) -> Self {
+        QueueCases {
+            cas: self.cas.clone(),
+            fresh: self.fresh.clone(),
+            last: self.last,
+            value: self.value.clone(),
+            pins: self.pins.clone(),
+        }
+    }
+}
+impl<T> Index<usize> for QueueCases<T> {
+    type Output = CasDesc<AtomicPtr<QueueNode<T>>>;
+    fn index(&self, i: usize) -> &Self::Output {
+        assert_eq!(i, 0);
+        self.cas.as_ref().expect("no cas")
+    }
+}
+impl<T> CasDescriptors<CasDesc<AtomicPtr<QueueNode<T>>>> for QueueCases<T> {
+    fn len(&self) -> usize {
+        self.cas.is_some() as usize
+    }
+}
+
+impl<T: Clone> NormalizedLockFree for LockFreeQueue<T> {
+    type Input = QueueOp<T>;
+    type Output = Option<T>;
+    type Cas = CasDesc<AtomicPtr<QueueNode<T>>>;
+    type Cases = QueueCases<T>;
+
+    fn generate(
+        &self,
+        op: &QueueOp<T>,
+        contention: &mut ContentionMeasure,
+    ) -> Result<QueueCases<T>, Contention> {
+        match op {
+            QueueOp::Enqueue(value) => {
+                let node = QueueNode::new(Some(value.clone()));
+                let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+                loop {
+                    // not dequeued yet while tail still points to it, head never passes tail.
+                    let last = load_node(&mut holder, &self.tail);
+                    let next = CasTarget::load(&unsafe { &*last }.next);
+                    if last != CasTarget::load(&self.tail) {
+                        contention.detected()?;
+                        continue;
+                    }
+                    if !next.is_null() {
+                        self.swing_tail(last, next);
+                        contention.detected()?;
+                        continue;
+                    }
+                    let node = Box::into_raw(node);
+                    // Safety: last is pinned by the cases.
+                    let link = unsafe { CasDesc::new(&(*last).next, ptr::null_mut(), node) };
+                    return Ok(QueueCases {
+                        cas: Some(link.clone()),
+                        fresh: Some(Arc::new(FreshNode { node, link })),
+                        last,
+                        value: None,
+                        pins: Some(unsafe { Pins::new(&[last]) }),
+                    });
+                }
+            }
+            QueueOp::Dequeue => {
+                let (mut first_holder, mut next_holder) =
+                    (HazPtrHolder::for_domain(&HELP_DOMAIN), HazPtrHolder::for_domain(&HELP_DOMAIN));
+                loop {
+                    let first = load_node(&mut first_holder, &self.head);
+                    let last = CasTarget::load(&self.tail);
+                    // next is safe to deref once head is seen at first again, below.
+                    let next = load_node(&mut next_holder, &unsafe { &*first }.next);
+                    if first != CasTarget::load(&self.head) {
+                        contention.detected()?;
+                        continue;
+                    }
+                    if first == last {
+                        if next.is_null() {
+                            return Ok(QueueCases {
+                                cas: None,
+                                fresh: None,
+                                last: ptr::null_mut(),
+                                value: None,
+                                pins: None,
+                            });
+                        }
+                        // never let head pass tail.
+                        self.swing_tail(last, next);
+                        contention.detected()?;
+                        continue;
+                    }
+                    // None when next was dequeued meanwhile too, the cas then fails anyway.
+                    let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+                    // Safety: values are Box::into_raw ptrs, retired into HELP_DOMAIN once swapped out.
+                    let value = unsafe { holder.load(&(*next).value) }.map(|value| value.0.clone());
+                    return Ok(QueueCases {
+                        // Safety: head is our own field, first is pinned by the cases.
+                        cas: Some(unsafe { CasDesc::new(&self.head, first, next) }),
+                        fresh: None,
+                        last: ptr::null_mut(),
+                        value,
+                        pins: Some(unsafe { Pins::new(&[first]) }),
+                    });
+                }
+            }
+        }
+    }
+    // enqueue: None. dequeue: the front value, None when empty.
+    fn wrap_up(&self, executed: Result<(), usize>, performed: &QueueCases<T>) -> Result<Option<T>, Contention> {
+        let Some(cas) = &performed.cas else {
+            return Ok(None);
+        };
+        executed.map_err(|_| Contention)?;
+        if let Some(fresh) = &performed.fresh {
+            // unless someone swung it past already.
+            self.swing_tail(performed.last, fresh.node);
+            return Ok(None);
+        }
+        // pinned by our cases.
+        let first = cas.expected();
+        if !unsafe { &*first }.dequeued.swap(true, Ordering::SeqCst) {
+            // returned by the dequeue before ours, only late generates may still clone it.
+            let value = unsafe { &*first }.value.swap(ptr::null_mut(), Ordering::SeqCst);
+            if !value.is_null() {
+                unsafe { retire(value) };
+            }
+            // taken out by the cas, the cases of other helpers still at it pin it.
+            unsafe { retire(first) };
+        }
+        Ok(performed.value.clone())
+    }
+}
+
+pub struct WaitFreeQueue<T: Clone> {
+    simulator: WaitFreeSimulator<LockFreeQueue<T>>,
+}
+impl<T: Clone> WaitFreeQueue<T> {
+    pub fn new() -> Self {
+        WaitFreeQueue {
+            simulator: WaitFreeSimulator::new(LockFreeQueue::new()),
+        }
+    }
+    pub fn enqueue(&self, value: T) {
+        self.simulator.run(QueueOp::Enqueue(value));
+    }
+    pub fn dequeue(&self) -> Option<T> {
+        self.simulator.run(QueueOp::Dequeue)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.simulator.algorithm.is_empty()
+    }
+}
+impl<T: Clone> Default for WaitFreeQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::linearizability::{self, assert_linearizable, QueueSpec, Recorder, SetOp, SetSpec};
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    // fetch_add on a counter as one cas. A starved thread generates a cas doomed to fail,
+    // so its op completes only when another thread helps it.
+    // The input is carried along and ignored, records clone it on every state change.
+    // While contended, every generate reports contention before it reads the counter.
+    struct Counter<I = ()> {
+        value: Arc<AtomicUsize>,
+        starved: Mutex<Option<thread::ThreadId>>,
+        contended: AtomicBool,
+        _input: std::marker::PhantomData<I>,
+    }
+    impl<I> Default for Counter<I> {
+        fn default() -> Self {
+            Counter {
+                value: Arc::default(),
+                starved: Mutex::default(),
+                contended: AtomicBool::new(false),
+                _input: std::marker::PhantomData,
+            }
+        }
+    }
+    impl<I: Clone> NormalizedLockFree for Counter<I> {
+        type Input = I;
+        type Output = usize;
+        type Cas = CasDesc<AtomicUsize>;
+        type Cases = CasDescs<AtomicUsize>;
+        fn generate(&self, _: &I, contention: &mut ContentionMeasure) -> Result<Self::Cases, Contention> {
+            if self.contended.load(Ordering::SeqCst) {
+                contention.detected()?;
+            }
+            let v = CasTarget::load(&*self.value);
+            let starved = *self.starved.lock().unwrap() == Some(thread::current().id());
+            // the counter never gets that far.
+            let expected = if starved { usize::MAX >> 1 } else { v };
+            // Safety: the simulator only executes cases while self, and so value, is alive.
+            Ok(CasDescs(vec![unsafe { CasDesc::new(&*self.value, expected, v + 1) }]))
+        }
+        fn wrap_up(&self, executed: Result<(), usize>, performed: &Self::Cases) -> Result<usize, Contention> {
+            executed.map(|_| performed[0].expected()).map_err(|_| Contention)
+        }
+    }
+
+    fn orb(input: ()) -> *const OperationRecordBox<Counter> {
+        OperationRecordBox::new(OperationRecord {
+            owner: thread::current().id(),
+            input,
+            state: OperationState::PreCas,
+        })
+    }
+
+    // helpers race to execute one desc while another thread keeps resetting the target to
+    // expected, a late helper must not perform the cas a second time.
+    #[test]
+    fn cas_desc_executes_once() {
+        const ROUNDS: usize = if cfg!(miri) { 5 } else { 200 };
+        for _ in 0..ROUNDS {
+            let target = AtomicUsize::new(0);
+            let desc = unsafe { CasDesc::new(&target, 0, 1) };
+            let (helping, resets) = (AtomicUsize::new(3), AtomicUsize::new(0));
+            thread::scope(|s| {
+                for _ in 0..3 {
+                    s.spawn(|| {
+                        assert_eq!(desc.clone().execute(), Ok(()));
+                        helping.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                s.spawn(|| {
+                    while helping.load(Ordering::SeqCst) > 0 {
+                        if unsafe { CasDesc::new(&target, 1, 0) }.execute().is_ok() {
+                            resets.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                });
+            });
+            assert_eq!(desc.state(), CasState::Success);
+            assert_eq!(resets.load(Ordering::SeqCst) + CasTarget::load(&target), 1);
+        }
+        let ptrs = [0u16; 2];
+        let target = AtomicPtr::new(&ptrs[0] as *const u16 as *mut u16);
+        let desc = unsafe { CasDesc::new(&target, &ptrs[1] as *const _ as *mut _, ptr::null_mut()) };
+        assert_eq!(desc.execute(), Err(()));
+        assert_eq!(desc.state(), CasState::Failure);
+        assert_eq!(CasTarget::load(&target), &ptrs[0] as *const _ as *mut _);
+    }
+
+    // a value with the tag bit would read back as an attempt, refused in release builds too.
+    #[test]
+    #[should_panic(expected = "CasTarget values stay below the top bit")]
+    fn cas_desc_rejects_tagged_value() {
+        let target = AtomicUsize::new(0);
+        let _ = unsafe { CasDesc::new(&target, 0, USIZE_TAG) }.execute();
+    }
+
+    #[test]
+    fn help_queue_conditional_remove() {
+        let queue = HelpQueue::<Counter>::new();
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        assert!(queue.peek(&mut holder).is_none());
+        let (a, b) = (orb(()), orb(()));
+        queue.enqueue(a);
+        queue.enqueue(b);
+        assert_eq!(queue.peek(&mut holder), Some(a));
+        // only the front goes.
+        assert!(queue.try_remove_front(b).is_err());
+        assert!(queue.try_remove_front(a).is_ok());
+        assert!(queue.try_remove_front(a).is_err());
+        assert_eq!(queue.peek(&mut holder), Some(b));
+        assert!(queue.try_remove_front(b).is_ok());
+        assert!(queue.peek(&mut holder).is_none());
+        assert!(queue.try_remove_front(b).is_err());
+        unsafe { OperationRecordBox::release(a) };
+        unsafe { OperationRecordBox::release(b) };
+    }
+
+    // every orb is enqueued once and removed from the front exactly once.
+    #[test]
+    fn help_queue_threads_remove_each_once() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = if cfg!(miri) { 10 } else { 500 };
+        let queue = HelpQueue::<Counter>::new();
+        let removed = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+                    for _ in 0..PER_THREAD {
+                        let orb = orb(());
+                        queue.enqueue(orb);
+                        // remove someone's front, then let go of our own orb.
+                        if let Some(front) = queue.peek(&mut holder) {
+                            if queue.try_remove_front(front).is_ok() {
+                                removed.fetch_add(1, Ordering::SeqCst);
+                            }
+                        }
+                        unsafe { OperationRecordBox::release(orb) };
+                    }
+                });
+            }
+        });
+        let mut holder = HazPtrHolder::for_domain(&HELP_DOMAIN);
+        while let Some(front) = queue.peek(&mut holder) {
+            queue.try_remove_front(front).expect("only we remove now");
+            removed.fetch_add(1, Ordering::SeqCst);
+        }
+        assert_eq!(removed.load(Ordering::SeqCst), THREADS * PER_THREAD);
+    }
+
+    #[test]
+    fn starved_thread_completes() {
+        let sim = WaitFreeSimulator::new(Counter::default());
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            let starved = s.spawn(|| {
+                *sim.algorithm.starved.lock().unwrap() = Some(thread::current().id());
+                let old = sim.run(());
+                done.store(true, Ordering::SeqCst);
+                old
+            });
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while !done.load(Ordering::SeqCst) {
+                        sim.run(());
+                    }
+                });
+            }
+            // its own fast path never succeeds, only the slow path with help gets it done.
+            let old = starved.join().unwrap();
+            assert!(old < sim.algorithm.value.load(Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn event_log_one_op() {
+        let sim = WaitFreeSimulator::new(Counter::default());
+        assert_eq!(sim.run_slow(()), 0);
+        assert!(sim.events().is_empty());
+
+        let sim = WaitFreeSimulator::new(Counter::default()).event_log();
+        assert_eq!(sim.run_slow(()), 0);
+        let me = thread::current().id();
+        let transitions: Vec<_> = sim.events().iter().map(|e| (e.from, e.to)).collect();
+        assert_eq!(
+            transitions,
+            [
+                (None, StateKind::PreCas),
+                (Some(StateKind::PreCas), StateKind::ExecuteCas),
+                (Some(StateKind::ExecuteCas), StateKind::PostCas),
+                (Some(StateKind::PostCas), StateKind::Completed),
+            ]
+        );
+        assert!(sim.events().iter().all(|e| e.owner == me && e.helper == me && e.cas_failed.is_none()));
+        let timeline = sim.timeline();
+        assert_eq!(timeline.lines().count(), 4);
+        assert!(timeline.lines().last().unwrap().ends_with("PostCas -> Completed"));
+    }
+
+    // the starved op's events chain up from enqueued to Completed, helped by others.
+    #[test]
+    fn event_log_starved_op() {
+        let sim = WaitFreeSimulator::new(Counter::default()).event_log();
+        let done = AtomicBool::new(false);
+        let starved = thread::scope(|s| {
+            let starved = s.spawn(|| {
+                *sim.algorithm.starved.lock().unwrap() = Some(thread::current().id());
+                sim.run(());
+                done.store(true, Ordering::SeqCst);
+                thread::current().id()
+            });
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while !done.load(Ordering::SeqCst) {
+                        sim.run(());
+                    }
+                });
+            }
+            starved.join().unwrap()
+        });
+        let events: Vec<_> = sim.events().into_iter().filter(|e| e.owner == starved).collect();
+        assert_eq!(events[0].from, None);
+        assert_eq!(events.last().unwrap().to, StateKind::Completed);
+        for pair in events.windows(2) {
+            assert!(pair[0].seq < pair[1].seq);
+            assert_eq!(pair[1].from, Some(pair[0].to), "{}", sim.timeline());
+        }
+        // its own cas is doomed, someone else generated the one that went through.
+        assert!(events.iter().any(|e| e.helper != starved));
+    }
+
+    #[test]
+    fn help_every_k_ops() {
+        let sim = WaitFreeSimulator::new(Counter::default()).help_every(3);
+        let completed = |orb: *const OperationRecordBox<Counter>| {
+            let record = unsafe { &*(*orb).val.load(Ordering::SeqCst) };
+            matches!(record.state, OperationState::Completed(_))
+        };
+        let other = WaitFreeSimulator::new(Counter::default()).help_every(3);
+        // a fresh thread, its help counter starts at 0.
+        thread::scope(|s| {
+            s.spawn(|| {
+                // counted per simulator, ops on another one bring no help closer.
+                other.run(());
+                other.run(());
+                let queued = orb(());
+                sim.help.enqueue(queued);
+                sim.run(());
+                sim.run(());
+                assert!(!completed(queued));
+                // the third op helps the queued one first.
+                assert_eq!(sim.run(()), 3);
+                assert!(completed(queued));
+                unsafe { OperationRecordBox::release(queued) };
+            });
+        });
+    }
+
+    // how k trades fast path throughput for the latency of a starved op.
+    // cargo test -- --ignored --nocapture bench_help_every
+    #[test]
+    #[ignore]
+    fn bench_help_every() {
+        const STARVED_OPS: usize = 100;
+        for k in [1, 4, 16, 64] {
+            let sim = WaitFreeSimulator::new(Counter::default()).help_every(k);
+            let (done, fast_ops) = (AtomicBool::new(false), AtomicUsize::new(0));
+            let start = std::time::Instant::now();
+            let worst = thread::scope(|s| {
+                for _ in 0..3 {
+                    s.spawn(|| {
+                        while !done.load(Ordering::SeqCst) {
+                            sim.run(());
+                            fast_ops.fetch_add(1, Ordering::Relaxed);
+                        }
+                    });
+                }
+                let starved = s.spawn(|| {
+                    *sim.algorithm.starved.lock().unwrap() = Some(thread::current().id());
+                    let worst = (0..STARVED_OPS)
+                        .map(|_| {
+                            let op = std::time::Instant::now();
+                            sim.run(());
+                            op.elapsed()
+                        })
+                        .max()
+                        .unwrap();
+                    done.store(true, Ordering::SeqCst);
+                    worst
+                });
+                starved.join().unwrap()
+            });
+            let elapsed = start.elapsed();
+            println!(
+                "help every {:>2} ops: {:.2} Mops/s others, starved op worst {:?}",
+                k,
+                fast_ops.load(Ordering::Relaxed) as f64 / elapsed.as_secs_f64() / 1e6,
+                worst
+            );
+        }
+    }
+
+    // every record clone of the input must be dropped exactly once, the magic catches a
+    // record read after it was reclaimed.
+    const MAGIC: usize = 0x5eed;
+    struct CountDrops {
+        magic: usize,
+        created: Arc<AtomicUsize>,
+        dropped: Arc<AtomicUsize>,
+    }
+    impl CountDrops {
+        fn new(created: &Arc<AtomicUsize>, dropped: &Arc<AtomicUsize>) -> Self {
+            created.fetch_add(1, Ordering::SeqCst);
+            CountDrops { magic: MAGIC, created: Arc::clone(created), dropped: Arc::clone(dropped) }
+        }
+    }
+    impl Clone for CountDrops {
+        fn clone(&self) -> Self {
+            assert_eq!(self.magic, MAGIC);
+            CountDrops::new(&self.created, &self.dropped)
+        }
+    }
+    impl Drop for CountDrops {
+        fn drop(&mut self) {
+            assert_eq!(self.magic, MAGIC);
+            self.magic = 0;
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // other tests share HELP_DOMAIN: their hazptrs, or a reclaim of theirs running on another
+    // thread, may hold on to our retired objects for a while. Reclaim until settled.
+    fn reclaim_until(settled: impl Fn() -> bool) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !settled() {
+            assert!(std::time::Instant::now() < deadline, "retired objects never reclaimed");
+            HELP_DOMAIN.eager_reclaim(false);
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn records_no_leak_no_use_after_free() {
+        const THREADS: usize = 4;
+        const OPS: usize = if cfg!(miri) { 20 } else { 500 };
+        // each starved op waits for a helper to come by, keep them few.
+        const STARVED_OPS: usize = OPS / 10;
+        let (created, dropped) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let sim = WaitFreeSimulator::new(Counter::<CountDrops>::default());
+        let (runs, starved_done) = (AtomicUsize::new(0), AtomicBool::new(false));
+        thread::scope(|s| {
+            for t in 0..THREADS {
+                let (sim, created, dropped) = (&sim, &created, &dropped);
+                let (runs, starved_done) = (&runs, &starved_done);
+                s.spawn(move || {
+                    // one starved thread keeps the slow path busy, the others keep going
+                    // until it is done, it can not complete its ops alone.
+                    if t == 0 {
+                        *sim.algorithm.starved.lock().unwrap() = Some(thread::current().id());
+                    }
+                    let ops = if t == 0 { STARVED_OPS } else { OPS };
+                    let mut i = 0;
+                    while i < ops || (t != 0 && !starved_done.load(Ordering::SeqCst)) {
+                        sim.run(CountDrops::new(created, dropped));
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        i += 1;
+                    }
+                    if t == 0 {
+                        starved_done.store(true, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(sim.algorithm.value.load(Ordering::SeqCst), runs.load(Ordering::SeqCst));
+        drop(sim);
+        reclaim_until(|| created.load(Ordering::SeqCst) == dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn contention_threshold_picks_path() {
+        let sim = WaitFreeSimulator::new(Counter::default()).contention_threshold(3);
+        for _ in 0..5 {
+            sim.run(());
+        }
+        assert_eq!(sim.path_stats(), PathStats { fast: 5, slow: 0 });
+        // some contention is tolerated.
+        sim.algorithm.contended.store(true, Ordering::SeqCst);
+        assert_eq!(sim.run(()), 5);
+        assert_eq!(sim.path_stats(), PathStats { fast: 6, slow: 0 });
+        // past the threshold the op gives up on the fast path, the slow path never does.
+        let sim = WaitFreeSimulator::new(Counter::default()).contention_threshold(0);
+        sim.algorithm.contended.store(true, Ordering::SeqCst);
+        assert_eq!(sim.run(()), 0);
+        assert_eq!(sim.path_stats(), PathStats { fast: 0, slow: 1 });
+
+        let mut contention = ContentionMeasure::new(3);
+        assert!((0..3).all(|_| contention.detected().is_ok()));
+        assert!(contention.detected().is_err());
+        assert_eq!(contention.count(), 4);
+
+        // threshold 0, the starved thread takes the slow path on its first failed cas.
+        let sim = WaitFreeSimulator::new(Counter::default()).contention_threshold(0);
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                *sim.algorithm.starved.lock().unwrap() = Some(thread::current().id());
+                for _ in 0..3 {
+                    sim.run(());
+                }
+                done.store(true, Ordering::SeqCst);
+            });
+            s.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    sim.run(());
+                }
+            });
+        });
+        let stats = sim.path_stats();
+        assert!(stats.slow >= 3);
+        assert_eq!(stats.fast + stats.slow, sim.algorithm.value.load(Ordering::SeqCst));
+        assert!(stats.slow_ratio() > 0.0);
+    }
+
+    #[test]
+    fn linked_list_set() {
+        let list = WaitFreeLinkedList::new();
+        assert!(list.insert(3));
+        assert!(list.insert(1));
+        assert!(!list.insert(3));
+        assert!(list.simulator.run_slow(ListOp::Insert(2)));
+        assert!(!list.simulator.run_slow(ListOp::Insert(2)));
+        assert!(list.contains(&1) && list.contains(&2) && list.contains(&3));
+        assert!(!list.contains(&4));
+        assert!(list.remove(2));
+        assert!(!list.remove(2));
+        assert!(list.simulator.run_slow(ListOp::Remove(1)));
+        assert!(!list.simulator.run_slow(ListOp::Remove(1)));
+        assert!(!list.contains(&1) && !list.contains(&2) && list.contains(&3));
+    }
+
+    // removed nodes go back through HELP_DOMAIN while the list lives on.
+    #[test]
+    fn linked_list_reclaims_removed() {
+        let list = WaitFreeLinkedList::new();
+        let token = Arc::new(());
+        for key in 0..10 {
+            assert!(list.insert((key, Arc::clone(&token))));
+        }
+        for key in 0..10 {
+            assert!(list.remove((key, Arc::clone(&token))));
+        }
+        // the search of this insert snips what is left marked.
+        assert!(list.insert((10, Arc::clone(&token))));
+        reclaim_until(|| Arc::strong_count(&token) == 2);
+        assert!(list.insert((5, Arc::clone(&token))) && list.remove((10, Arc::clone(&token))));
+        assert!(list.contains(&(5, Arc::clone(&token))) && !list.contains(&(10, Arc::clone(&token))));
+        drop(list);
+        reclaim_until(|| Arc::strong_count(&token) == 1);
+    }
+
+    #[test]
+    fn linked_list_linearizable() {
+        const THREADS: usize = 3;
+        const OPS: usize = if cfg!(miri) { 10 } else { 40 };
+        const KEYS: usize = 4;
+        const ROUNDS: usize = if cfg!(miri) { 1 } else { 20 };
+        for round in 0..ROUNDS {
+            let list = WaitFreeLinkedList::new();
+            let recorder = Recorder::<SetSpec<usize>>::new();
+            thread::scope(|s| {
+                for t in 0..THREADS {
+                    let (list, recorder) = (&list, &recorder);
+                    s.spawn(move || {
+                        for i in 0..OPS {
+                            let key = (i * 7 + t * 3 + round) % KEYS;
+                            let op = [SetOp::Insert(key), SetOp::Remove(key), SetOp::Contains(key)];
+                            // every third op goes down the slow path, so helpers race on it.
+                            let slow = i % 3 == t % 3;
+                            recorder.record(op[(i + t) % 3].clone(), |op| match (op, slow) {
+                                (&SetOp::Insert(key), false) => list.insert(key),
+                                (&SetOp::Insert(key), true) => list.simulator.run_slow(ListOp::Insert(key)),
+                                (&SetOp::Remove(key), false) => list.remove(key),
+                                (&SetOp::Remove(key), true) => list.simulator.run_slow(ListOp::Remove(key)),
+                                (SetOp::Contains(key), _) => list.contains(key),
+                            });
+                        }
+                    });
+                }
+            });
+            assert_linearizable(&SetSpec::default(), &recorder.history());
+        }
+    }
+
+    #[test]
+    fn queue_linearizable() {
+        const THREADS: usize = 3;
+        const OPS: usize = if cfg!(miri) { 6 } else { 16 };
+        const ROUNDS: usize = if cfg!(miri) { 1 } else { 20 };
+        for round in 0..ROUNDS {
+            let queue = WaitFreeQueue::new();
+            let recorder = Recorder::<QueueSpec<usize>>::new();
+            thread::scope(|s| {
+                for t in 0..THREADS {
+                    let (queue, recorder) = (&queue, &recorder);
+                    s.spawn(move || {
+                        for i in 0..OPS {
+                            let op = if (i + t + round) % 2 == 0 {
+                                linearizability::QueueOp::Enqueue(t * OPS + i)
+                            } else {
+                                linearizability::QueueOp::Dequeue
+                            };
+                            let slow = i % 3 == t % 3;
+                            recorder.record(op, |op| match (op, slow) {
+                                (&linearizability::QueueOp::Enqueue(v), false) => {
+                                    queue.enqueue(v);
+                                    None
+                                }
+                                (&linearizability::QueueOp::Enqueue(v), true) => {
+                                    queue.simulator.run_slow(QueueOp::Enqueue(v));
+                                    None
+                                }
+                                (linearizability::QueueOp::Dequeue, false) => queue.dequeue(),
+                                (linearizability::QueueOp::Dequeue, true) => {
+                                    queue.simulator.run_slow(QueueOp::Dequeue)
+                                }
+                            });
+                        }
+                    });
+                }
+            });
+            assert_linearizable(&QueueSpec::default(), &recorder.history());
+        }
+    }
+
+    #[test]
+    fn queue_fifo() {
+        let queue = WaitFreeQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.dequeue(), None);
+        queue.enqueue(1);
+        queue.simulator.run_slow(QueueOp::Enqueue(2));
+        queue.enqueue(3);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.dequeue(), Some(1));
+        assert_eq!(queue.simulator.run_slow(QueueOp::Dequeue), Some(2));
+        assert_eq!(queue.dequeue(), Some(3));
+        assert_eq!(queue.simulator.run_slow(QueueOp::Dequeue), None);
+        assert!(queue.is_empty());
+        // values left in the queue are dropped with it.
+        let queue = WaitFreeQueue::new();
+        let value = Arc::new(());
+        queue.enqueue(Arc::clone(&value));
+        queue.enqueue(Arc::clone(&value));
+        drop(queue.dequeue());
+        drop(queue);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    // dequeued nodes and values go back through HELP_DOMAIN, only the sentinel keeps its own.
+    #[test]
+    fn queue_reclaims_dequeued_values() {
+        let queue = WaitFreeQueue::new();
+        let value = Arc::new(());
+        for _ in 0..3 {
+            queue.enqueue(Arc::clone(&value));
+        }
+        for _ in 0..3 {
+            drop(queue.dequeue());
+        }
+        reclaim_until(|| Arc::strong_count(&value) == 2);
+        queue.enqueue(Arc::clone(&value));
+        assert!(queue.dequeue().is_some());
+        drop(queue);
+        reclaim_until(|| Arc::strong_count(&value) == 1);
+    }
+
+    // producers enqueue (producer, seq), consumers check every value comes out once and the
+    // values of each producer in order. Every fifth op takes the slow path.
+    fn queue_imbalance(producers: usize, consumers: usize) {
+        const PER_PRODUCER: usize = if cfg!(miri) { 20 } else { 1_000 };
+        let queue = WaitFreeQueue::new();
+        let dequeued = AtomicUsize::new(0);
+        let seen = Mutex::new(vec![vec![false; PER_PRODUCER]; producers]);
+        thread::scope(|s| {
+            for p in 0..producers {
+                let queue = &queue;
+                s.spawn(move || {
+                    for seq in 0..PER_PRODUCER {
+                        if seq % 5 == 0 {
+                            queue.simulator.run_slow(QueueOp::Enqueue((p, seq)));
+                        } else {
+                            queue.enqueue((p, seq));
+                        }
+                    }
+                });
+            }
+            for _ in 0..consumers {
+                let (queue, dequeued, seen) = (&queue, &dequeued, &seen);
+                s.spawn(move || {
+                    let mut last = vec![None; producers];
+                    let mut i = 0;
+                    while dequeued.load(Ordering::SeqCst) < producers * PER_PRODUCER {
+                        i += 1;
+                        let value = if i % 5 == 0 {
+                            queue.simulator.run_slow(QueueOp::Dequeue)
+                        } else {
+                            queue.dequeue()
+                        };
+                        let Some((p, seq)) = value else {
+                            continue;
+                        };
+                        assert!(last[p] < Some(seq), "producer {} out of order", p);
+                        last[p] = Some(seq);
+                        assert!(!std::mem::replace(&mut seen.lock().unwrap()[p][seq], true));
+                        dequeued.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert!(queue.is_empty());
+        assert!(seen.into_inner().unwrap().iter().flatten().all(|&seen| seen));
+    }
+
+    #[test]
+    fn queue_one_producer_many_consumers() {
+        queue_imbalance(1, 6);
+    }
+
+    #[test]
+    fn queue_many_producers_one_consumer() {
+        queue_imbalance(6, 1);
+    }
+}
+
+// Bounded, not exhaustive: sync::model stops at 2 preemptions per execution, and
+// helper_completes_slow_op also at a time budget. A pass covers the interleavings within
+// those bounds only.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+
+    // fetch_add on a counter as one cas, the smallest op there is to help. A full queue op
+    // runs thousands of atomic ops, past what loom explores in reasonable time.
+    #[derive(Default)]
+    struct Counter {
+        value: AtomicUsize,
+    }
+    impl NormalizedLockFree for Counter {
+        type Input = ();
+        type Output = usize;
+        type Cas = CasDesc<AtomicUsize>;
+        type Cases = CasDescs<AtomicUsize>;
+        fn generate(&self, _: &(), _: &mut ContentionMeasure) -> Result<Self::Cases, Contention> {
+            let v = CasTarget::load(&self.value);
+            // Safety: the simulator only executes cases while self, and so value, is alive.
+            Ok(CasDescs(vec![unsafe { CasDesc::new(&self.value, v, v + 1) }]))
+        }
+        fn wrap_up(&self, executed: Result<(), usize>, performed: &Self::Cases) -> Result<usize, Contention> {
+            executed.map(|_| performed[0].expected()).map_err(|_| Contention)
+        }
+    }
+
+    // two helpers execute the same desc while a third thread resets the target to expected
+    // once it saw the cas. A late helper finds the decision taken and does not redo the cas.
+    #[test]
+    fn cas_desc_executes_once() {
+        sync::model(|| {
+            let target = Arc::new(AtomicUsize::new(0));
+            let desc = unsafe { CasDesc::new(&*target, 0, 1) };
+            let helpers: Vec<_> = (0..2)
+                .map(|_| {
+                    let (target, desc) = (Arc::clone(&target), desc.clone());
+                    thread::spawn(move || {
+                        assert_eq!(desc.execute(), Ok(()));
+                        drop(target);
+                    })
+                })
+                .collect();
+            let reset = unsafe { CasDesc::new(&*target, 1, 0) }.execute().is_ok();
+            for helper in helpers {
+                helper.join().unwrap();
+            }
+            assert_eq!(desc.state(), CasState::Success);
+            assert_eq!(reset as usize + CasTarget::load(&*target), 1);
+        });
+    }
+
+    // a slow path op and another thread helping the front of the queue. Whether the helper
+    // finds the op queued, generated, executed or done, its cas runs once. The op alone is
+    // ~1500 atomic ops, far too many interleavings to go through them all.
+    #[test]
+    fn helper_completes_slow_op() {
+        sync::model_for(std::time::Duration::from_secs(60), || {
+            let sim = Arc::new(WaitFreeSimulator::new(Counter::default()));
+            let slow = thread::spawn({
+                let sim = Arc::clone(&sim);
+                move || sim.run_slow(())
+            });
+            sim.help_first();
+            assert_eq!(slow.join().unwrap(), 0);
+            assert_eq!(CasTarget::load(&sim.algorithm.value), 1);
+        });
+    }
+}